@@ -1,7 +1,8 @@
 use crate::{executor, table};
 use serde::{Deserialize, Serialize};
+use serde_json;
 use std::cmp::{Ord, Ordering};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 #[derive(Debug, PartialEq)]
@@ -10,27 +11,278 @@ pub enum Ast {
     Create(TableSchema),
     Insert(Insertion),
     Select(Selection),
+    Truncate(String),
+    Vacuum,
+    Alter(Alteration),
+    /// Describes how a statement would be executed without executing it
+    Explain(Box<Ast>),
+    Savepoint(String),
+    ReleaseSavepoint(String),
+    RollbackTo(String),
+}
+
+impl Ast {
+    /// Substitutes every parameter in the statement with its bound value
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Ast, String> {
+        match self {
+            Ast::Exit => Ok(Ast::Exit),
+            Ast::Create(schema) => Ok(Ast::Create(schema.clone())),
+            Ast::Insert(insertion) => insertion.bind(parameters).map(Ast::Insert),
+            Ast::Select(selection) => selection.bind(parameters).map(Ast::Select),
+            Ast::Truncate(table_name) => Ok(Ast::Truncate(table_name.clone())),
+            Ast::Vacuum => Ok(Ast::Vacuum),
+            Ast::Alter(alteration) => alteration.bind(parameters).map(Ast::Alter),
+            Ast::Explain(ast) => ast.bind(parameters).map(|ast| Ast::Explain(Box::new(ast))),
+            Ast::Savepoint(name) => Ok(Ast::Savepoint(name.clone())),
+            Ast::ReleaseSavepoint(name) => Ok(Ast::ReleaseSavepoint(name.clone())),
+            Ast::RollbackTo(name) => Ok(Ast::RollbackTo(name.clone())),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ColumnSet {
     WildCard,
     Names(Vec<String>),
+    /// `COUNT(*)`, which selects a single row with the number of matches
+    Count,
+    /// Columns where at least one is worked out from an expression rather
+    /// than just named
+    Expressions(Vec<Expression>),
+}
+
+impl ColumnSet {
+    /// Names if every expression is a column and Expressions otherwise
+    pub fn from_expressions(expressions: Vec<Expression>) -> ColumnSet {
+        let mut names = vec![];
+        for expression in &expressions {
+            match expression {
+                Expression::Column(name) => names.push(name.clone()),
+                _ => return ColumnSet::Expressions(expressions),
+            }
+        }
+        ColumnSet::Names(names)
+    }
+
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<ColumnSet, String> {
+        match self {
+            ColumnSet::Expressions(expressions) => expressions
+                .iter()
+                .map(|expression| expression.bind(parameters))
+                .collect::<Result<Vec<Expression>, String>>()
+                .map(ColumnSet::Expressions),
+            columns => Ok(columns.clone()),
+        }
+    }
+}
+
+/// Value worked out for each row
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Expression {
+    Column(String),
+    Value(Value),
+    /// `CAST(expr AS to)`
+    Cast {
+        expr: Box<Expression>,
+        to: DataType,
+    },
+}
+
+impl Expression {
+    pub fn cast(expr: Expression, to: DataType) -> Expression {
+        Expression::Cast {
+            expr: Box::new(expr),
+            to,
+        }
+    }
+
+    /// Every column the expression depends on
+    pub fn columns(&self) -> Vec<&String> {
+        match self {
+            Expression::Column(column) => vec![column],
+            Expression::Value(_) => vec![],
+            Expression::Cast { expr, .. } => expr.columns(),
+        }
+    }
+
+    pub fn rename_columns<F: Fn(&str) -> String>(&self, rename: &F) -> Expression {
+        match self {
+            Expression::Column(column) => Expression::Column(rename(column)),
+            Expression::Value(_) => self.clone(),
+            Expression::Cast { expr, to } => Expression::cast(expr.rename_columns(rename), *to),
+        }
+    }
+
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Expression, String> {
+        Ok(match self {
+            Expression::Column(_) => self.clone(),
+            Expression::Value(value) => Expression::Value(value.bind(parameters)?),
+            Expression::Cast { expr, to } => Expression::cast(expr.bind(parameters)?, *to),
+        })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Column(column) => write!(f, "{}", column),
+            Expression::Value(Value::Text(text)) => write!(f, "'{}'", text.replace('\'', "''")),
+            Expression::Value(value) => write!(f, "{}", value),
+            Expression::Cast { expr, to } => write!(f, "CAST({} AS {})", expr, to),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Value {
     Integer(i64),
+    Text(String),
+    Boolean(bool),
+    Blob(Vec<u8>),
     Null,
+    /// Placeholder in a prepared statement, numbered from 1
+    Parameter(usize),
 }
 
+impl Value {
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Value, String> {
+        match self {
+            Value::Parameter(index) => parameters
+                .get(index)
+                .cloned()
+                .ok_or_else(|| format!("unbound parameter ?{}", index)),
+            value => Ok(value.clone()),
+        }
+    }
+
+    /// Value converted to `to` like SQLite's CAST. NULL stays NULL, and so
+    /// does text that can't be read as an integer or a boolean.
+    pub fn cast(&self, to: DataType) -> Value {
+        match (self, to) {
+            (Value::Null, _) | (Value::Parameter(_), _) | (_, DataType::Any) => self.clone(),
+            (Value::Integer(_), DataType::Integer) => self.clone(),
+            (Value::Boolean(b), DataType::Integer) => Value::Integer(*b as i64),
+            (Value::Text(text), DataType::Integer) => text
+                .trim()
+                .parse::<i64>()
+                .map_or(Value::Null, Value::Integer),
+            (Value::Boolean(_), DataType::Boolean) => self.clone(),
+            (Value::Integer(i), DataType::Boolean) => Value::Boolean(*i != 0),
+            (Value::Text(text), DataType::Boolean) => {
+                match text.trim().to_ascii_lowercase().as_str() {
+                    "true" => Value::Boolean(true),
+                    "false" => Value::Boolean(false),
+                    _ => Value::Text(text.clone())
+                        .cast(DataType::Integer)
+                        .cast(DataType::Boolean),
+                }
+            }
+            (Value::Text(_), DataType::Text) => self.clone(),
+            (Value::Blob(bytes), DataType::Text) => {
+                Value::Text(String::from_utf8_lossy(bytes).into_owned())
+            }
+            (value, DataType::Text) => Value::Text(value.to_string()),
+            (Value::Blob(_), DataType::Blob) => self.clone(),
+            (Value::Text(text), DataType::Blob) => Value::Blob(text.as_bytes().to_vec()),
+            (value, DataType::Blob) => value.cast(DataType::Text).cast(DataType::Blob),
+            (Value::Blob(_), to) => self.cast(DataType::Text).cast(to),
+        }
+    }
+
+    /// Parameter numbered by the digits of `number`, which must be at least 1
+    pub fn parameter_from_number(number: &str) -> Result<Value, &'static str> {
+        match number.parse::<usize>() {
+            Ok(0) => Err("parameters are numbered from ?1"),
+            Ok(index) => Ok(Value::Parameter(index)),
+            Err(_) => Err("parameter number is too large"),
+        }
+    }
+
+    /// Blob with the bytes written as pairs of hexadecimal digits in `hex`
+    pub fn blob_from_hex(hex: &str) -> Result<Value, &'static str> {
+        if hex.len() % 2 != 0 {
+            return Err("hex literal must have an even number of digits");
+        }
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("hex literal may only contain hexadecimal digits");
+        }
+
+        Ok(Value::Blob(
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect(),
+        ))
+    }
+
+    /// JSON representation of the value, where a blob is an array of its
+    /// bytes. Fails for a parameter, which has no value until it is bound.
+    pub fn to_json(&self) -> Result<serde_json::Value, String> {
+        Ok(match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Integer(i) => serde_json::Value::from(*i),
+            Value::Text(s) => serde_json::Value::from(s.clone()),
+            Value::Boolean(b) => serde_json::Value::from(*b),
+            Value::Blob(bytes) => serde_json::Value::Array(
+                bytes
+                    .iter()
+                    .map(|byte| serde_json::Value::from(*byte as i64))
+                    .collect(),
+            ),
+            Value::Parameter(index) => return Err(format!("unbound parameter ?{}", index)),
+        })
+    }
+
+    /// Value represented by `json` as returned by `to_json`, if any
+    pub fn from_json(json: &serde_json::Value) -> Option<Value> {
+        match json {
+            serde_json::Value::Null => Some(Value::Null),
+            serde_json::Value::Number(n) => n.as_i64().map(Value::Integer),
+            serde_json::Value::String(s) => Some(Value::Text(s.clone())),
+            serde_json::Value::Bool(b) => Some(Value::Boolean(*b)),
+            serde_json::Value::Array(bytes) => bytes
+                .iter()
+                .map(|byte| match byte {
+                    serde_json::Value::Number(n) => match n.as_i64() {
+                        Some(byte) if byte >= 0 && byte <= 255 => Some(byte as u8),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Option<Vec<u8>>>()
+                .map(Value::Blob),
+            _ => None,
+        }
+    }
+
+    /// Rank used to order values of different variants. Booleans sort
+    /// after every integer and before any text, so they never compare equal
+    /// to 0 or 1. Blobs sort after text.
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Integer(_) => 0,
+            Value::Boolean(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+            Value::Null => 4,
+            Value::Parameter(_) => 5,
+        }
+    }
+}
+
+/// NULL is greater than every other value. This order is fixed because the
+/// B+ trees of tables are keyed by it, so NULLs always come last in key order
+/// and in primary key ranges. Sorting with ORDER BY places NULLs according to
+/// the executor's `ComparisonMode` instead.
 impl Ord for Value {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
-            (Value::Null, Value::Null) => Ordering::Equal,
-            (Value::Integer(_), Value::Null) => Ordering::Less,
-            (Value::Null, Value::Integer(_)) => Ordering::Greater,
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            (Value::Parameter(a), Value::Parameter(b)) => a.cmp(b),
+            (a, b) => a.rank().cmp(&b.rank()),
         }
     }
 }
@@ -45,7 +297,252 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
             Value::Integer(i) => write!(f, "{}", i),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Blob(bytes) => {
+                write!(f, "X'")?;
+                for byte in bytes {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
             Value::Null => write!(f, "null"),
+            Value::Parameter(index) => write!(f, "?{}", index),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Operator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Comparison(String, Operator, Value),
+    /// Comparison between two columns of the same row, or between a column
+    /// of a subquery and one of the query around it
+    ColumnComparison(String, Operator, String),
+    In(String, Vec<Value>),
+    /// Inclusive on both ends
+    Between(String, Value, Value),
+    IsNull(String),
+    IsNotNull(String),
+    /// Pattern where `%` matches any sequence of characters and `_` any
+    /// single character, unless preceded by the escape character
+    Like(String, String, Option<char>),
+    /// `column IN (SELECT ...)`, where the selection has a single column
+    InSubquery(String, Box<Selection>),
+    /// Comparison with the value of a selection that has a single column
+    /// and at most one row, or NULL if it has no rows
+    ComparisonSubquery(String, Operator, Box<Selection>),
+    /// Whether a selection has any rows, which may depend on the row of the
+    /// query around it
+    Exists(Box<Selection>),
+    /// Comparison of a value worked out from the row, such as a CAST
+    ExpressionComparison(Expression, Operator, Value),
+    /// Outcome of a predicate that was worked out ahead of time, such as an
+    /// EXISTS that doesn't depend on the row being filtered
+    Constant(bool),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn like(
+        column: String,
+        pattern: String,
+        escape: Option<String>,
+    ) -> Result<Predicate, &'static str> {
+        let escape = match escape {
+            None => None,
+            Some(escape) => {
+                let mut chars = escape.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => return Err("ESCAPE expression must be a single character"),
+                }
+            }
+        };
+        Ok(Predicate::Like(column, pattern, escape))
+    }
+
+    /// Every column referenced by the predicate
+    pub fn columns(&self) -> Vec<&String> {
+        match self {
+            Predicate::Comparison(column, _, _) => vec![column],
+            Predicate::ColumnComparison(left, _, right) => vec![left, right],
+            Predicate::In(column, _) => vec![column],
+            Predicate::Between(column, _, _) => vec![column],
+            Predicate::IsNull(column) | Predicate::IsNotNull(column) => vec![column],
+            Predicate::Like(column, _, _) => vec![column],
+            Predicate::InSubquery(column, _) => vec![column],
+            Predicate::ComparisonSubquery(column, _, _) => vec![column],
+            Predicate::ExpressionComparison(expression, _, _) => expression.columns(),
+            Predicate::Exists(_) | Predicate::Constant(_) => vec![],
+            Predicate::And(left, right) | Predicate::Or(left, right) => {
+                let mut columns = left.columns();
+                columns.extend(right.columns());
+                columns
+            }
+            Predicate::Not(predicate) => predicate.columns(),
+        }
+    }
+
+    /// Whether a selection has to be run to evaluate the predicate
+    pub fn has_subquery(&self) -> bool {
+        match self {
+            Predicate::InSubquery(_, _)
+            | Predicate::ComparisonSubquery(_, _, _)
+            | Predicate::Exists(_) => true,
+            Predicate::And(left, right) | Predicate::Or(left, right) => {
+                left.has_subquery() || right.has_subquery()
+            }
+            Predicate::Not(predicate) => predicate.has_subquery(),
+            _ => false,
+        }
+    }
+
+    /// Predicate with every column it references renamed by `rename`, apart
+    /// from the columns inside of subqueries
+    pub fn rename_columns<F: Fn(&str) -> String>(&self, rename: &F) -> Predicate {
+        match self {
+            Predicate::Comparison(column, operator, value) => {
+                Predicate::Comparison(rename(column), operator.clone(), value.clone())
+            }
+            Predicate::ColumnComparison(left, operator, right) => {
+                Predicate::ColumnComparison(rename(left), operator.clone(), rename(right))
+            }
+            Predicate::In(column, values) => Predicate::In(rename(column), values.clone()),
+            Predicate::Between(column, low, high) => {
+                Predicate::Between(rename(column), low.clone(), high.clone())
+            }
+            Predicate::IsNull(column) => Predicate::IsNull(rename(column)),
+            Predicate::IsNotNull(column) => Predicate::IsNotNull(rename(column)),
+            Predicate::Like(column, pattern, escape) => {
+                Predicate::Like(rename(column), pattern.clone(), *escape)
+            }
+            Predicate::InSubquery(column, selection) => {
+                Predicate::InSubquery(rename(column), selection.clone())
+            }
+            Predicate::ComparisonSubquery(column, operator, selection) => {
+                Predicate::ComparisonSubquery(rename(column), operator.clone(), selection.clone())
+            }
+            Predicate::ExpressionComparison(expression, operator, value) => {
+                Predicate::ExpressionComparison(
+                    expression.rename_columns(rename),
+                    operator.clone(),
+                    value.clone(),
+                )
+            }
+            Predicate::Exists(_) | Predicate::Constant(_) => self.clone(),
+            Predicate::And(left, right) => Predicate::And(
+                Box::new(left.rename_columns(rename)),
+                Box::new(right.rename_columns(rename)),
+            ),
+            Predicate::Or(left, right) => Predicate::Or(
+                Box::new(left.rename_columns(rename)),
+                Box::new(right.rename_columns(rename)),
+            ),
+            Predicate::Not(predicate) => Predicate::Not(Box::new(predicate.rename_columns(rename))),
+        }
+    }
+
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Predicate, String> {
+        let bind_all = |values: &Vec<Value>| {
+            values
+                .iter()
+                .map(|value| value.bind(parameters))
+                .collect::<Result<Vec<Value>, String>>()
+        };
+        Ok(match self {
+            Predicate::Comparison(column, operator, value) => {
+                Predicate::Comparison(column.clone(), operator.clone(), value.bind(parameters)?)
+            }
+            Predicate::ColumnComparison(_, _, _) | Predicate::Constant(_) => self.clone(),
+            Predicate::ExpressionComparison(expression, operator, value) => {
+                Predicate::ExpressionComparison(
+                    expression.bind(parameters)?,
+                    operator.clone(),
+                    value.bind(parameters)?,
+                )
+            }
+            Predicate::In(column, values) => Predicate::In(column.clone(), bind_all(values)?),
+            Predicate::Between(column, low, high) => Predicate::Between(
+                column.clone(),
+                low.bind(parameters)?,
+                high.bind(parameters)?,
+            ),
+            Predicate::IsNull(column) => Predicate::IsNull(column.clone()),
+            Predicate::IsNotNull(column) => Predicate::IsNotNull(column.clone()),
+            Predicate::Like(column, pattern, escape) => {
+                Predicate::Like(column.clone(), pattern.clone(), *escape)
+            }
+            Predicate::InSubquery(column, selection) => {
+                Predicate::InSubquery(column.clone(), Box::new(selection.bind(parameters)?))
+            }
+            Predicate::ComparisonSubquery(column, operator, selection) => {
+                Predicate::ComparisonSubquery(
+                    column.clone(),
+                    operator.clone(),
+                    Box::new(selection.bind(parameters)?),
+                )
+            }
+            Predicate::Exists(selection) => {
+                Predicate::Exists(Box::new(selection.bind(parameters)?))
+            }
+            Predicate::And(left, right) => Predicate::And(
+                Box::new(left.bind(parameters)?),
+                Box::new(right.bind(parameters)?),
+            ),
+            Predicate::Or(left, right) => Predicate::Or(
+                Box::new(left.bind(parameters)?),
+                Box::new(right.bind(parameters)?),
+            ),
+            Predicate::Not(predicate) => Predicate::Not(Box::new(predicate.bind(parameters)?)),
+        })
+    }
+}
+
+/// Direction an ORDER BY key is sorted in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Whether NULLs are sorted before or after every other value of an ORDER BY
+/// key, regardless of its direction
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
+/// `column [COLLATE collation] [ASC | DESC] [NULLS FIRST | NULLS LAST]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+    pub column: String,
+    pub order: SortOrder,
+    /// Where NULLs go, or None to leave it to the executor's comparison mode
+    pub nulls: Option<NullsOrder>,
+    /// Collation to sort text with, or None to use the column's
+    pub collation: Option<Collation>,
+}
+
+impl OrderingTerm {
+    pub fn new(column: &str, order: SortOrder, nulls: Option<NullsOrder>) -> OrderingTerm {
+        OrderingTerm {
+            column: column.to_string(),
+            order,
+            nulls,
+            collation: None,
         }
     }
 }
@@ -54,6 +551,8 @@ impl fmt::Display for Value {
 pub struct Selection {
     table_name: String,
     columns: ColumnSet,
+    predicate: Option<Predicate>,
+    order_by: Vec<OrderingTerm>,
 }
 
 impl executor::Selection for Selection {
@@ -68,35 +567,143 @@ impl executor::Selection for Selection {
     fn columns(&self) -> ColumnSet {
         self.columns()
     }
+
+    fn predicate(&self) -> Option<Predicate> {
+        self.predicate.clone()
+    }
+
+    fn order_by(&self) -> Vec<OrderingTerm> {
+        self.order_by.clone()
+    }
 }
 
 impl Selection {
-    pub fn new(table_name: &str, columns: ColumnSet) -> Selection {
+    pub fn new(table_name: &str, columns: ColumnSet, predicate: Option<Predicate>) -> Selection {
+        Selection::new_with_order_by(table_name, columns, predicate, vec![])
+    }
+
+    pub fn new_with_order_by(
+        table_name: &str,
+        columns: ColumnSet,
+        predicate: Option<Predicate>,
+        order_by: Vec<OrderingTerm>,
+    ) -> Selection {
         Selection {
             table_name: table_name.to_string(),
             columns,
+            predicate,
+            order_by,
         }
     }
     pub fn validate(&self) -> Result<(), String> {
         return Ok(());
     }
 
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Selection, String> {
+        let predicate = match &self.predicate {
+            None => None,
+            Some(predicate) => Some(predicate.bind(parameters)?),
+        };
+
+        Ok(Selection::new_with_order_by(
+            &self.table_name,
+            self.columns.bind(parameters)?,
+            predicate,
+            self.order_by.clone(),
+        ))
+    }
+
     fn columns(&self) -> ColumnSet {
         self.columns.clone()
     }
 }
 
+/// Type a column is declared with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    Integer,
+    Text,
+    Boolean,
+    Blob,
+    /// Declared without a type
+    Any,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Integer => write!(f, "INTEGER"),
+            DataType::Text => write!(f, "TEXT"),
+            DataType::Boolean => write!(f, "BOOLEAN"),
+            DataType::Blob => write!(f, "BLOB"),
+            DataType::Any => write!(f, "ANY"),
+        }
+    }
+}
+
+/// How text values are compared with one another
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Collation {
+    /// Character by character
+    Binary,
+    /// Character by character with ASCII letters folded to lower case
+    NoCase,
+}
+
+impl Default for Collation {
+    fn default() -> Collation {
+        Collation::Binary
+    }
+}
+
+impl Collation {
+    /// Value that compares with other folded values the way `value` does
+    /// under this collation
+    pub fn key(&self, value: &Value) -> Value {
+        match (self, value) {
+            (Collation::NoCase, Value::Text(text)) => Value::Text(text.to_ascii_lowercase()),
+            _ => value.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
+    pub data_type: DataType,
+    /// Collation of the column, used for comparisons, ORDER BY and the
+    /// primary key
+    pub collation: Collation,
     pub is_primary_key: bool,
+    pub is_autoincrement: bool,
 }
 
 impl Column {
+    /// INTEGER column
     pub fn new(name: &str, is_primary_key: bool) -> Column {
+        Column::new_with_autoincrement(name, is_primary_key, false)
+    }
+
+    pub fn new_with_autoincrement(
+        name: &str,
+        is_primary_key: bool,
+        is_autoincrement: bool,
+    ) -> Column {
+        Column::new_with_data_type(name, DataType::Integer, is_primary_key, is_autoincrement)
+    }
+
+    pub fn new_with_data_type(
+        name: &str,
+        data_type: DataType,
+        is_primary_key: bool,
+        is_autoincrement: bool,
+    ) -> Column {
         Column {
             name: name.to_string(),
+            data_type,
+            collation: Collation::default(),
             is_primary_key,
+            is_autoincrement,
         }
     }
 }
@@ -105,15 +712,201 @@ impl Column {
 pub struct TableSchema {
     pub name: String,
     pub columns: Vec<Column>,
+    /// Columns of a table-level `PRIMARY KEY (a, b)` constraint, in key order
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<ForeignKey>,
+    /// Predicates of the column and table `CHECK` constraints, which every
+    /// row must not fail
+    pub checks: Vec<Predicate>,
 }
 
 impl TableSchema {
     pub fn new(name: &str, columns: Vec<Column>) -> TableSchema {
+        TableSchema::new_with_primary_key(name, columns, vec![])
+    }
+
+    pub fn new_with_primary_key(
+        name: &str,
+        columns: Vec<Column>,
+        primary_key: Vec<String>,
+    ) -> TableSchema {
         TableSchema {
             name: name.to_string(),
             columns,
+            primary_key,
+            foreign_keys: vec![],
+            checks: vec![],
+        }
+    }
+
+    /// Columns that rows are keyed by, which are the primary key columns or
+    /// else the first column
+    pub fn key_columns(&self) -> Vec<String> {
+        if !self.primary_key.is_empty() {
+            return self.primary_key.clone();
+        }
+        match self.columns.iter().find(|column| column.is_primary_key) {
+            Some(column) => vec![column.name.clone()],
+            None => self
+                .columns
+                .iter()
+                .take(1)
+                .map(|c| c.name.clone())
+                .collect(),
         }
     }
+
+    /// Schema made up of the column definitions and table constraints of a
+    /// CREATE TABLE statement
+    pub fn from_elements(
+        name: &str,
+        elements: Vec<TableElement>,
+    ) -> Result<TableSchema, &'static str> {
+        let mut columns = vec![];
+        let mut primary_key = None;
+        let mut foreign_keys = vec![];
+        let mut checks = vec![];
+        for element in elements {
+            match element {
+                TableElement::Column(column, column_checks) => {
+                    columns.push(column);
+                    checks.extend(column_checks);
+                }
+                TableElement::ForeignKey(foreign_key) => foreign_keys.push(foreign_key),
+                TableElement::Check(predicate) => checks.push(predicate),
+                TableElement::PrimaryKey(column_names) => {
+                    if primary_key.is_some() {
+                        return Err("table has more than one PRIMARY KEY constraint");
+                    }
+                    primary_key = Some(column_names);
+                }
+            }
+        }
+
+        let mut schema =
+            TableSchema::new_with_primary_key(name, columns, primary_key.unwrap_or_default());
+        schema.foreign_keys = foreign_keys;
+        schema.checks = checks;
+        Ok(schema)
+    }
+
+    /// Changes that would turn this schema into `desired`, matching columns
+    /// by name. Columns to add and retype come in the order of `desired`,
+    /// followed by the columns to drop in the order of this schema.
+    pub fn diff(&self, desired: &TableSchema) -> Vec<SchemaChange> {
+        let mut changes = vec![];
+        for column in &desired.columns {
+            match self.columns.iter().find(|c| c.name == column.name) {
+                None => changes.push(SchemaChange::AddColumn(column.clone())),
+                Some(existing) if existing.data_type != column.data_type => {
+                    changes.push(SchemaChange::ChangeType {
+                        column: column.name.clone(),
+                        from: existing.data_type,
+                        to: column.data_type,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for column in &self.columns {
+            if !desired.columns.iter().any(|c| c.name == column.name) {
+                changes.push(SchemaChange::DropColumn(column.name.clone()));
+            }
+        }
+        changes
+    }
+}
+
+/// One way a table differs from the schema it is meant to have
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    AddColumn(Column),
+    DropColumn(String),
+    ChangeType {
+        column: String,
+        from: DataType,
+        to: DataType,
+    },
+}
+
+/// Column definition or table constraint in a CREATE TABLE statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableElement {
+    /// Column definition with the predicates of its `CHECK` constraints
+    Column(Column, Vec<Predicate>),
+    PrimaryKey(Vec<String>),
+    ForeignKey(ForeignKey),
+    Check(Predicate),
+}
+
+/// What happens to the rows that reference a parent row when it is deleted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ForeignKeyAction {
+    /// The parent row can't be deleted
+    Restrict,
+    /// The referencing rows are deleted along with it
+    Cascade,
+}
+
+/// `FOREIGN KEY (columns) REFERENCES parent_table(parent_columns)`, where
+/// the parent columns must be the primary key of the parent table
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub parent_table: String,
+    pub parent_columns: Vec<String>,
+    pub on_delete: ForeignKeyAction,
+}
+
+impl ForeignKey {
+    pub fn new(
+        columns: Vec<String>,
+        parent_table: &str,
+        parent_columns: Vec<String>,
+        on_delete: ForeignKeyAction,
+    ) -> ForeignKey {
+        ForeignKey {
+            columns,
+            parent_table: parent_table.to_string(),
+            parent_columns,
+            on_delete,
+        }
+    }
+}
+
+/// Column added to an existing table, with the value existing rows are
+/// backfilled with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Alteration {
+    pub table_name: String,
+    pub column: Column,
+    pub default: Value,
+}
+
+impl Alteration {
+    pub fn new(table_name: &str, column: Column, default: Option<Value>) -> Alteration {
+        Alteration {
+            table_name: table_name.to_string(),
+            column,
+            default: default.unwrap_or(Value::Null),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.column.is_primary_key {
+            return Err("Cannot add a PRIMARY KEY column".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Alteration, String> {
+        Ok(Alteration::new(
+            &self.table_name,
+            self.column.clone(),
+            Some(self.default.bind(parameters)?),
+        ))
+    }
 }
 
 impl table::TableSchema for TableSchema {
@@ -121,11 +914,80 @@ impl table::TableSchema for TableSchema {
         self.name.clone()
     }
 
+    /// Columns of the table, where every column of a table-level primary
+    /// key is marked as part of the primary key
     fn columns(&self) -> Vec<Column> {
-        return self.columns.clone();
+        self.columns
+            .iter()
+            .map(|column| Column {
+                is_primary_key: column.is_primary_key || self.primary_key.contains(&column.name),
+                ..column.clone()
+            })
+            .collect()
+    }
+
+    fn foreign_keys(&self) -> Vec<ForeignKey> {
+        self.foreign_keys.clone()
+    }
+
+    fn checks(&self) -> Vec<Predicate> {
+        self.checks.clone()
     }
 
     fn validate(&self) -> Result<(), String> {
+        for check in &self.checks {
+            if check.has_subquery() {
+                return Err("subqueries prohibited in CHECK constraints".to_string());
+            }
+            for column_name in check.columns() {
+                if !self
+                    .columns
+                    .iter()
+                    .any(|column| column.name == *column_name)
+                {
+                    return Err(format!("no such column: {}", column_name));
+                }
+            }
+        }
+        for foreign_key in &self.foreign_keys {
+            if foreign_key.columns.len() != foreign_key.parent_columns.len() {
+                return Err(format!(
+                    "foreign key on table \"{}\" has {} columns but references {}",
+                    self.name,
+                    foreign_key.columns.len(),
+                    foreign_key.parent_columns.len()
+                ));
+            }
+            for column_name in &foreign_key.columns {
+                if !self
+                    .columns
+                    .iter()
+                    .any(|column| column.name == *column_name)
+                {
+                    return Err(format!("no such column: {}", column_name));
+                }
+            }
+        }
+        if !self.primary_key.is_empty() && self.columns.iter().any(|column| column.is_primary_key) {
+            return Err(format!(
+                "table \"{}\" has more than one primary key",
+                self.name
+            ));
+        }
+        let mut key_column_names = HashSet::new();
+        for column_name in &self.primary_key {
+            if !self
+                .columns
+                .iter()
+                .any(|column| column.name == *column_name)
+            {
+                return Err(format!("no such column: {}", column_name));
+            }
+            if !key_column_names.insert(column_name) {
+                return Err(format!("duplicate column in primary key: {}", column_name));
+            }
+        }
+
         let mut column_names = HashSet::new();
         let mut has_primary_key = false;
         for c in &self.columns {
@@ -138,6 +1000,9 @@ impl table::TableSchema for TableSchema {
                     self.name
                 ));
             }
+            if c.is_autoincrement && !c.is_primary_key {
+                return Err("AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY".to_string());
+            }
             if c.is_primary_key {
                 has_primary_key = true
             }
@@ -152,6 +1017,7 @@ pub struct Insertion {
     pub table_name: String,
     column_names: Option<Vec<String>>,
     values: Vec<Value>,
+    returning: Option<ColumnSet>,
 }
 
 impl executor::Insertion for Insertion {
@@ -170,6 +1036,10 @@ impl executor::Insertion for Insertion {
     fn values(&self) -> Box<dyn Iterator<Item = Value>> {
         self.values()
     }
+
+    fn returning(&self) -> Option<ColumnSet> {
+        self.returning.clone()
+    }
 }
 
 impl Insertion {
@@ -177,6 +1047,15 @@ impl Insertion {
         table_name: &str,
         column_names: Option<Vec<String>>,
         values: Vec<Value>,
+    ) -> Insertion {
+        Insertion::new_with_returning(table_name, column_names, values, None)
+    }
+
+    pub fn new_with_returning(
+        table_name: &str,
+        column_names: Option<Vec<String>>,
+        values: Vec<Value>,
+        returning: Option<ColumnSet>,
     ) -> Insertion {
         return Insertion {
             table_name: table_name.to_string(),
@@ -187,6 +1066,7 @@ impl Insertion {
                     .collect()
             }),
             values,
+            returning,
         };
     }
     pub fn validate(&self) -> Result<(), String> {
@@ -216,6 +1096,20 @@ impl Insertion {
     pub fn values(&self) -> Box<dyn Iterator<Item = Value>> {
         Box::new(self.values.clone().into_iter())
     }
+    pub fn bind(&self, parameters: &HashMap<usize, Value>) -> Result<Insertion, String> {
+        let values = self
+            .values
+            .iter()
+            .map(|value| value.bind(parameters))
+            .collect::<Result<Vec<Value>, String>>()?;
+
+        Ok(Insertion::new_with_returning(
+            &self.table_name,
+            self.column_names.clone(),
+            values,
+            self.returning.clone(),
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -254,11 +1148,736 @@ mod test_parsing {
                 insert_stmt,
                 Ast::Create(TableSchema {
                     name: "apples".to_string(),
-                    columns: vec![Column::new("slices", false)]
+                    columns: vec![Column::new("slices", false)],
+                    primary_key: vec![],
+                    foreign_keys: vec![],
+                    checks: vec![],
+                })
+            )
+        }
+    }
+
+    #[test]
+    fn truncate_table_statement() {
+        let parse_result = sqlite3::AstParser::new().parse("TRUNCATE TABLE apples;");
+        assert_eq!(parse_result, Ok(Ast::Truncate("apples".to_string())));
+    }
+
+    #[test]
+    fn insert_statement_with_returning() {
+        let parse_result = sqlite3::AstParser::new()
+            .parse("INSERT INTO apples(slices) VALUES(8) RETURNING id, slices;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Insert(Insertion::new_with_returning(
+                "apples",
+                Some(vec!["slices".to_string()]),
+                vec![Value::Integer(8)],
+                Some(ColumnSet::Names(vec![
+                    "id".to_string(),
+                    "slices".to_string()
+                ]))
+            )))
+        );
+
+        let parse_result =
+            sqlite3::AstParser::new().parse("INSERT INTO apples VALUES(1, 8) RETURNING *;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Insert(Insertion::new_with_returning(
+                "apples",
+                None,
+                vec![Value::Integer(1), Value::Integer(8)],
+                Some(ColumnSet::WildCard)
+            )))
+        );
+    }
+
+    #[test]
+    fn count_statement() {
+        let parse_result = sqlite3::AstParser::new().parse("SELECT COUNT(*) FROM apples;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new(
+                "apples",
+                ColumnSet::Count,
+                None
+            )))
+        );
+    }
+
+    #[test]
+    fn vacuum_statement() {
+        let parse_result = sqlite3::AstParser::new().parse("VACUUM;");
+        assert_eq!(parse_result, Ok(Ast::Vacuum));
+    }
+
+    #[test]
+    fn savepoint_statements() {
+        let parse = |statement| sqlite3::AstParser::new().parse(statement);
+        assert_eq!(
+            parse("SAVEPOINT sp1;"),
+            Ok(Ast::Savepoint("sp1".to_string()))
+        );
+        assert_eq!(
+            parse("RELEASE sp1;"),
+            Ok(Ast::ReleaseSavepoint("sp1".to_string()))
+        );
+        assert_eq!(
+            parse("RELEASE SAVEPOINT sp1;"),
+            Ok(Ast::ReleaseSavepoint("sp1".to_string()))
+        );
+        assert_eq!(
+            parse("ROLLBACK TO sp1;"),
+            Ok(Ast::RollbackTo("sp1".to_string()))
+        );
+        assert_eq!(
+            parse("rollback to savepoint sp1;"),
+            Ok(Ast::RollbackTo("sp1".to_string()))
+        );
+    }
+
+    #[test]
+    fn alter_table_statement() {
+        let parse_result =
+            sqlite3::AstParser::new().parse("ALTER TABLE apples ADD COLUMN seeds INTEGER;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Alter(Alteration::new(
+                "apples",
+                Column::new("seeds", false),
+                None
+            )))
+        );
+    }
+
+    #[test]
+    fn explain_select_statement() {
+        let parse_result = sqlite3::AstParser::new().parse("EXPLAIN SELECT * FROM apples;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Explain(Box::new(Ast::Select(Selection::new(
+                "apples",
+                ColumnSet::WildCard,
+                None
+            )))))
+        );
+    }
+
+    #[test]
+    fn alter_table_statement_with_default() {
+        let parse_result = sqlite3::AstParser::new()
+            .parse("ALTER TABLE apples ADD COLUMN seeds INTEGER DEFAULT 12;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Alter(Alteration::new(
+                "apples",
+                Column::new("seeds", false),
+                Some(Value::Integer(12))
+            )))
+        );
+    }
+
+    #[test]
+    fn create_table_statement_with_autoincrement() {
+        let statement =
+            "CREATE TABLE apples(id INTEGER PRIMARY KEY AUTOINCREMENT, slices INTEGER);";
+        let parse_result = sqlite3::AstParser::new().parse(statement);
+        if parse_result.is_err() {
+            parse_result.expect("should parse create table statement");
+        } else {
+            let create_stmt = parse_result.unwrap();
+            assert_eq!(
+                create_stmt,
+                Ast::Create(TableSchema {
+                    name: "apples".to_string(),
+                    columns: vec![
+                        Column::new_with_autoincrement("id", true, true),
+                        Column::new("slices", false)
+                    ],
+                    primary_key: vec![],
+                    foreign_keys: vec![],
+                    checks: vec![],
                 })
             )
         }
     }
+
+    #[test]
+    fn create_table_statement_keeps_declared_types() {
+        let parse_result = sqlite3::AstParser::new()
+            .parse("CREATE TABLE fruit(name TEXT, ripe BOOLEAN, photo BLOB, weight);");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Create(TableSchema::new(
+                "fruit",
+                vec![
+                    Column::new_with_data_type("name", DataType::Text, false, false),
+                    Column::new_with_data_type("ripe", DataType::Boolean, false, false),
+                    Column::new_with_data_type("photo", DataType::Blob, false, false),
+                    Column::new_with_data_type("weight", DataType::Any, false, false),
+                ]
+            )))
+        );
+    }
+
+    #[test]
+    fn create_table_statement_with_collations() {
+        let parse_result = sqlite3::AstParser::new().parse(
+            "CREATE TABLE fruit(name TEXT COLLATE NOCASE PRIMARY KEY, variety TEXT collate binary, \
+             color TEXT);",
+        );
+        let mut name = Column::new_with_data_type("name", DataType::Text, true, false);
+        name.collation = Collation::NoCase;
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Create(TableSchema::new(
+                "fruit",
+                vec![
+                    name,
+                    Column::new_with_data_type("variety", DataType::Text, false, false),
+                    Column::new_with_data_type("color", DataType::Text, false, false),
+                ]
+            )))
+        );
+
+        assert!(sqlite3::AstParser::new()
+            .parse("CREATE TABLE fruit(name TEXT COLLATE RTRIM);")
+            .is_err());
+    }
+
+    #[test]
+    fn create_table_statement_with_composite_primary_key() {
+        let parse_result = sqlite3::AstParser::new()
+            .parse("CREATE TABLE orchards(farm INTEGER, row INTEGER, trees INTEGER, PRIMARY KEY (farm, row));");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Create(TableSchema::new_with_primary_key(
+                "orchards",
+                vec![
+                    Column::new("farm", false),
+                    Column::new("row", false),
+                    Column::new("trees", false)
+                ],
+                vec!["farm".to_string(), "row".to_string()]
+            )))
+        );
+
+        assert!(sqlite3::AstParser::new()
+            .parse("CREATE TABLE orchards(farm INTEGER, PRIMARY KEY (farm), PRIMARY KEY (farm));")
+            .is_err());
+    }
+
+    #[test]
+    fn create_table_statement_with_foreign_keys() {
+        let parse_result = sqlite3::AstParser::new().parse(
+            "CREATE TABLE trees(id INTEGER PRIMARY KEY, farm INTEGER, \
+             FOREIGN KEY (farm) REFERENCES farms (id) ON DELETE CASCADE, \
+             FOREIGN KEY (id) REFERENCES stumps (id));",
+        );
+        let mut schema = TableSchema::new(
+            "trees",
+            vec![Column::new("id", true), Column::new("farm", false)],
+        );
+        schema.foreign_keys = vec![
+            ForeignKey::new(
+                vec!["farm".to_string()],
+                "farms",
+                vec!["id".to_string()],
+                ForeignKeyAction::Cascade,
+            ),
+            ForeignKey::new(
+                vec!["id".to_string()],
+                "stumps",
+                vec!["id".to_string()],
+                ForeignKeyAction::Restrict,
+            ),
+        ];
+        assert_eq!(parse_result, Ok(Ast::Create(schema)));
+    }
+
+    #[test]
+    fn select_statement_with_casts() {
+        let parse_result = sqlite3::AstParser::new()
+            .parse("SELECT id, CAST(slices AS TEXT), CAST('12' AS INTEGER) FROM apples;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new(
+                "apples",
+                ColumnSet::Expressions(vec![
+                    Expression::Column("id".to_string()),
+                    Expression::cast(Expression::Column("slices".to_string()), DataType::Text),
+                    Expression::cast(
+                        Expression::Value(Value::Text("12".to_string())),
+                        DataType::Integer
+                    ),
+                ]),
+                None
+            )))
+        );
+
+        let parse_result = sqlite3::AstParser::new().parse("SELECT id, slices FROM apples;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new(
+                "apples",
+                ColumnSet::Names(vec!["id".to_string(), "slices".to_string()]),
+                None
+            )))
+        );
+
+        assert!(sqlite3::AstParser::new()
+            .parse("SELECT CAST(slices) FROM apples;")
+            .is_err());
+    }
+
+    #[test]
+    fn select_statement_with_order_by() {
+        let parse_result = sqlite3::AstParser::new().parse(
+            "SELECT * FROM apples ORDER BY slices DESC NULLS LAST, seeds, id ASC NULLS LAST, \
+             stem desc nulls first;",
+        );
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new_with_order_by(
+                "apples",
+                ColumnSet::WildCard,
+                None,
+                vec![
+                    OrderingTerm::new("slices", SortOrder::Desc, Some(NullsOrder::Last)),
+                    OrderingTerm::new("seeds", SortOrder::Asc, None),
+                    OrderingTerm::new("id", SortOrder::Asc, Some(NullsOrder::Last)),
+                    OrderingTerm::new("stem", SortOrder::Desc, Some(NullsOrder::First)),
+                ]
+            )))
+        );
+
+        let parse_result =
+            sqlite3::AstParser::new().parse("SELECT id FROM apples WHERE id > 1 ORDER BY id DESC;");
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new_with_order_by(
+                "apples",
+                ColumnSet::Names(vec!["id".to_string()]),
+                Some(Predicate::Comparison(
+                    "id".to_string(),
+                    Operator::Gt,
+                    Value::Integer(1)
+                )),
+                vec![OrderingTerm::new("id", SortOrder::Desc, None)]
+            )))
+        );
+    }
+
+    #[test]
+    fn select_statement_with_order_by_collation() {
+        let parse_result = sqlite3::AstParser::new().parse(
+            "SELECT * FROM fruit ORDER BY name COLLATE NOCASE DESC, variety COLLATE BINARY;",
+        );
+        let mut name = OrderingTerm::new("name", SortOrder::Desc, None);
+        name.collation = Some(Collation::NoCase);
+        let mut variety = OrderingTerm::new("variety", SortOrder::Asc, None);
+        variety.collation = Some(Collation::Binary);
+        assert_eq!(
+            parse_result,
+            Ok(Ast::Select(Selection::new_with_order_by(
+                "fruit",
+                ColumnSet::WildCard,
+                None,
+                vec![name, variety]
+            )))
+        );
+    }
+
+    #[test]
+    fn create_table_statement_with_check_constraints() {
+        let parse_result = sqlite3::AstParser::new().parse(
+            "CREATE TABLE people(id INTEGER PRIMARY KEY, age INTEGER CHECK(age >= 0), \
+             CHECK (id < 100 OR age IS NULL));",
+        );
+        let mut schema = TableSchema::new(
+            "people",
+            vec![Column::new("id", true), Column::new("age", false)],
+        );
+        schema.checks = vec![
+            Predicate::Comparison("age".to_string(), Operator::GtEq, Value::Integer(0)),
+            Predicate::Or(
+                Box::new(Predicate::Comparison(
+                    "id".to_string(),
+                    Operator::Lt,
+                    Value::Integer(100),
+                )),
+                Box::new(Predicate::IsNull("age".to_string())),
+            ),
+        ];
+        assert_eq!(parse_result, Ok(Ast::Create(schema)));
+    }
+}
+
+#[cfg(test)]
+mod test_predicate_parsing {
+    use super::*;
+    use crate::sqlite3;
+
+    fn parse_predicate(statement: &str) -> Option<Predicate> {
+        match sqlite3::AstParser::new().parse(statement) {
+            Ok(Ast::Select(selection)) => selection.predicate,
+            result => panic!("should parse select statement: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE slices >= 4;"),
+            Some(Predicate::Comparison(
+                "slices".to_string(),
+                Operator::GtEq,
+                Value::Integer(4)
+            ))
+        );
+    }
+
+    #[test]
+    fn in_list() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE id IN (1, 2, 3);"),
+            Some(Predicate::In(
+                "id".to_string(),
+                vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]
+            ))
+        );
+    }
+
+    #[test]
+    fn empty_in_list() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE id IN ();"),
+            Some(Predicate::In("id".to_string(), vec![]))
+        );
+    }
+
+    #[test]
+    fn in_subquery() {
+        assert_eq!(
+            parse_predicate(
+                "SELECT * FROM apples WHERE id IN (SELECT apple FROM seeds WHERE size > 2);"
+            ),
+            Some(Predicate::InSubquery(
+                "id".to_string(),
+                Box::new(Selection::new(
+                    "seeds",
+                    ColumnSet::Names(vec!["apple".to_string()]),
+                    Some(Predicate::Comparison(
+                        "size".to_string(),
+                        Operator::Gt,
+                        Value::Integer(2)
+                    ))
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn exists_and_not_exists() {
+        let children = || {
+            Box::new(Selection::new(
+                "trees",
+                ColumnSet::WildCard,
+                Some(Predicate::ColumnComparison(
+                    "trees.farm".to_string(),
+                    Operator::Eq,
+                    "farms.id".to_string(),
+                )),
+            ))
+        };
+        assert_eq!(
+            parse_predicate(
+                "SELECT * FROM farms WHERE EXISTS (SELECT * FROM trees WHERE trees.farm = farms.id);"
+            ),
+            Some(Predicate::Exists(children()))
+        );
+        assert_eq!(
+            parse_predicate(
+                "SELECT * FROM farms WHERE acres > 2 AND NOT EXISTS \
+                 (SELECT * FROM trees WHERE trees.farm = farms.id);"
+            ),
+            Some(Predicate::And(
+                Box::new(Predicate::Comparison(
+                    "acres".to_string(),
+                    Operator::Gt,
+                    Value::Integer(2)
+                )),
+                Box::new(Predicate::Not(Box::new(Predicate::Exists(children()))))
+            ))
+        );
+    }
+
+    #[test]
+    fn scalar_subquery() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE slices = (SELECT COUNT(*) FROM seeds);"),
+            Some(Predicate::ComparisonSubquery(
+                "slices".to_string(),
+                Operator::Eq,
+                Box::new(Selection::new("seeds", ColumnSet::Count, None))
+            ))
+        );
+    }
+
+    #[test]
+    fn between() {
+        assert_eq!(
+            parse_predicate("SELECT id FROM apples WHERE id BETWEEN 5 AND 10;"),
+            Some(Predicate::Between(
+                "id".to_string(),
+                Value::Integer(5),
+                Value::Integer(10)
+            ))
+        );
+    }
+
+    #[test]
+    fn is_null() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE slices IS NULL;"),
+            Some(Predicate::IsNull("slices".to_string()))
+        );
+    }
+
+    #[test]
+    fn is_not_null() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE slices is not null;"),
+            Some(Predicate::IsNotNull("slices".to_string()))
+        );
+    }
+
+    #[test]
+    fn like() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE name LIKE 'Gr%';"),
+            Some(Predicate::Like("name".to_string(), "Gr%".to_string(), None))
+        );
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE name NOT LIKE 'it''s _';"),
+            Some(Predicate::Not(Box::new(Predicate::Like(
+                "name".to_string(),
+                "it's _".to_string(),
+                None
+            ))))
+        );
+    }
+
+    #[test]
+    fn like_with_escape() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE name LIKE '100\\%' ESCAPE '\\';"),
+            Some(Predicate::Like(
+                "name".to_string(),
+                "100\\%".to_string(),
+                Some('\\')
+            ))
+        );
+        assert!(sqlite3::AstParser::new()
+            .parse("SELECT * FROM apples WHERE name LIKE 'a' ESCAPE 'ab';")
+            .is_err());
+    }
+
+    #[test]
+    fn boolean_values() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE ripe = TRUE OR ripe = false;"),
+            Some(Predicate::Or(
+                Box::new(Predicate::Comparison(
+                    "ripe".to_string(),
+                    Operator::Eq,
+                    Value::Boolean(true)
+                )),
+                Box::new(Predicate::Comparison(
+                    "ripe".to_string(),
+                    Operator::Eq,
+                    Value::Boolean(false)
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn blob_values() {
+        let blob = Value::Blob(b"Hello".to_vec());
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE photo = X'48656C6c6F';"),
+            Some(Predicate::Comparison(
+                "photo".to_string(),
+                Operator::Eq,
+                blob.clone()
+            ))
+        );
+        assert_eq!(blob.to_string(), "X'48656C6C6F'");
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE photo = x'';"),
+            Some(Predicate::Comparison(
+                "photo".to_string(),
+                Operator::Eq,
+                Value::Blob(vec![])
+            ))
+        );
+
+        for malformed in &["X'486'", "X'4G'"] {
+            let statement = format!("SELECT * FROM apples WHERE photo = {};", malformed);
+            assert!(sqlite3::AstParser::new().parse(&statement).is_err());
+        }
+    }
+
+    #[test]
+    fn text_value() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE name = 'Fuji';"),
+            Some(Predicate::Comparison(
+                "name".to_string(),
+                Operator::Eq,
+                Value::Text("Fuji".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn cast_comparison() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE CAST(slices AS TEXT) < '9';"),
+            Some(Predicate::ExpressionComparison(
+                Expression::cast(Expression::Column("slices".to_string()), DataType::Text),
+                Operator::Lt,
+                Value::Text("9".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_predicate(
+                "SELECT * FROM apples WHERE cast(cast(apples.name as integer) as boolean) = TRUE;"
+            ),
+            Some(Predicate::ExpressionComparison(
+                Expression::cast(
+                    Expression::cast(
+                        Expression::Column("apples.name".to_string()),
+                        DataType::Integer
+                    ),
+                    DataType::Boolean
+                ),
+                Operator::Eq,
+                Value::Boolean(true)
+            ))
+        );
+    }
+
+    fn equals(column: &str, value: i64) -> Box<Predicate> {
+        Box::new(Predicate::Comparison(
+            column.to_string(),
+            Operator::Eq,
+            Value::Integer(value),
+        ))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE a=1 OR b=2 AND c=3;"),
+            Some(Predicate::Or(
+                equals("a", 1),
+                Box::new(Predicate::And(equals("b", 2), equals("c", 3)))
+            ))
+        );
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE NOT a=1 AND b=2;"),
+            Some(Predicate::And(
+                Box::new(Predicate::Not(equals("a", 1))),
+                equals("b", 2)
+            ))
+        );
+    }
+
+    #[test]
+    fn parentheses_group_predicates() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE (a=1 OR b=2) AND c=3;"),
+            Some(Predicate::And(
+                Box::new(Predicate::Or(equals("a", 1), equals("b", 2))),
+                equals("c", 3)
+            ))
+        );
+    }
+
+    #[test]
+    fn between_inside_and() {
+        assert_eq!(
+            parse_predicate("SELECT * FROM apples WHERE a BETWEEN 1 AND 2 AND c=3;"),
+            Some(Predicate::And(
+                Box::new(Predicate::Between(
+                    "a".to_string(),
+                    Value::Integer(1),
+                    Value::Integer(2)
+                )),
+                equals("c", 3)
+            ))
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_cast {
+    use super::*;
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    #[test]
+    fn values_are_cast_like_sqlite() {
+        assert_eq!(Value::Integer(42).cast(DataType::Text), text("42"));
+        assert_eq!(text(" -7 ").cast(DataType::Integer), Value::Integer(-7));
+        assert_eq!(text("7 apples").cast(DataType::Integer), Value::Null);
+        assert_eq!(
+            Value::Boolean(true).cast(DataType::Integer),
+            Value::Integer(1)
+        );
+        assert_eq!(
+            Value::Integer(0).cast(DataType::Boolean),
+            Value::Boolean(false)
+        );
+        assert_eq!(text("TRUE").cast(DataType::Boolean), Value::Boolean(true));
+        assert_eq!(text("2").cast(DataType::Boolean), Value::Boolean(true));
+        assert_eq!(text("maybe").cast(DataType::Boolean), Value::Null);
+        assert_eq!(text("Hi").cast(DataType::Blob), Value::Blob(b"Hi".to_vec()));
+        assert_eq!(
+            Value::Blob(b"12".to_vec()).cast(DataType::Integer),
+            Value::Integer(12)
+        );
+        assert_eq!(text("12").cast(DataType::Any), text("12"));
+        for data_type in &[
+            DataType::Integer,
+            DataType::Text,
+            DataType::Boolean,
+            DataType::Blob,
+        ] {
+            assert_eq!(Value::Null.cast(*data_type), Value::Null);
+        }
+    }
+
+    #[test]
+    fn expressions_are_displayed_as_sql() {
+        let expression = Expression::cast(
+            Expression::cast(Expression::Value(text("it's")), DataType::Blob),
+            DataType::Text,
+        );
+        assert_eq!(
+            expression.to_string(),
+            "CAST(CAST('it''s' AS BLOB) AS TEXT)"
+        );
+    }
 }
 
 #[cfg(test)]
@@ -304,4 +1923,90 @@ mod test_table_schema {
         let result = table_schema.validate();
         assert_eq!(result.is_err(), true);
     }
+
+    #[test]
+    fn validation_fails_if_inline_and_table_level_primary_keys_are_mixed() {
+        let table_schema = super::TableSchema::new_with_primary_key(
+            "orchards",
+            vec![Column::new("farm", true), Column::new("row", false)],
+            vec!["farm".to_string(), "row".to_string()],
+        );
+        assert_eq!(
+            table_schema.validate(),
+            Err("table \"orchards\" has more than one primary key".to_string())
+        );
+    }
+
+    #[test]
+    fn validation_fails_if_the_primary_key_has_an_unknown_column() {
+        let table_schema = super::TableSchema::new_with_primary_key(
+            "orchards",
+            vec![Column::new("farm", false)],
+            vec!["farm".to_string(), "row".to_string()],
+        );
+        assert_eq!(
+            table_schema.validate(),
+            Err("no such column: row".to_string())
+        );
+    }
+
+    #[test]
+    fn validation_fails_if_autoincrement_is_not_on_a_primary_key() {
+        let table_schema = super::TableSchema::new(
+            "kings",
+            vec![Column::new_with_autoincrement("henry", false, true)],
+        );
+
+        let result = table_schema.validate();
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn diff_lists_the_columns_to_add_drop_and_retype() {
+        let live = super::TableSchema::new(
+            "apples",
+            vec![
+                Column::new("id", true),
+                Column::new("slices", false),
+                Column::new("seeds", false),
+            ],
+        );
+        let desired = super::TableSchema::new(
+            "apples",
+            vec![
+                Column::new("id", true),
+                Column::new_with_data_type("variety", DataType::Text, false, false),
+                Column::new_with_data_type("slices", DataType::Text, false, false),
+            ],
+        );
+
+        assert_eq!(live.diff(&live), vec![]);
+        assert_eq!(
+            live.diff(&desired),
+            vec![
+                SchemaChange::AddColumn(Column::new_with_data_type(
+                    "variety",
+                    DataType::Text,
+                    false,
+                    false
+                )),
+                SchemaChange::ChangeType {
+                    column: "slices".to_string(),
+                    from: DataType::Integer,
+                    to: DataType::Text,
+                },
+                SchemaChange::DropColumn("seeds".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn validation_fails_if_a_check_constraint_has_an_unknown_column() {
+        let mut table_schema = super::TableSchema::new("people", vec![Column::new("age", false)]);
+        table_schema.checks = vec![Predicate::IsNotNull("height".to_string())];
+        assert_eq!(
+            table_schema.validate(),
+            Err("no such column: height".to_string())
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use super::bp_tree_node::{BPTreeNode, InternalNode, LeafNode};
+use super::bp_tree_node::{BPTreeNode, InternalNode, LeafIter, LeafNode};
 use super::Serializer;
 use super::{Entry, Key, Value};
 use std::cell::RefCell;
@@ -27,6 +27,59 @@ impl<K: Key + 'static, V: Value + 'static> BPTree<K, V> {
             root_node: None,
         }
     }
+    /// Like `new`, but checks that `order`, the most children a node may
+    /// have, is at least 3. Nodes hold at most `order - 1` keys and are split
+    /// sooner if their entries take up `page_byte_size` bytes.
+    pub fn with_order(
+        order: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+    ) -> Result<BPTree<K, V>, String> {
+        if order < 3 {
+            return Err(format!("order must be at least 3 but got {}", order));
+        }
+
+        Ok(BPTree::new(order, page_byte_size, serializer))
+    }
+    /// Builds a tree from entries sorted by key in a single pass, which is
+    /// faster than inserting them one at a time and leaves the nodes full
+    pub fn bulk_load<I: Iterator<Item = (K, V)>>(
+        degree: usize,
+        page_byte_size: usize,
+        serializer: Serializer,
+        entries: I,
+    ) -> Result<BPTree<K, V>, String> {
+        let mut bptree = BPTree::new(degree, page_byte_size, serializer);
+        bptree.load(entries)?;
+        Ok(bptree)
+    }
+
+    /// Replaces every entry of the tree with entries sorted by key, built in
+    /// a single pass like `bulk_load`. The tree is left as it was if the
+    /// entries aren't sorted.
+    pub fn load<I: Iterator<Item = (K, V)>>(&mut self, entries: I) -> Result<(), String> {
+        let mut sorted_entries: Vec<Entry<K, V>> = vec![];
+        for (key, value) in entries {
+            if let Some(previous) = sorted_entries.last() {
+                if previous.key >= key {
+                    return Err(format!(
+                        "entries must be sorted by key without duplicates but {} came after {}",
+                        key, previous.key
+                    ));
+                }
+            }
+            sorted_entries.push(Entry::new(key, value));
+        }
+
+        self.root_node = BPTreeNode::bulk_load(
+            sorted_entries,
+            self.degree,
+            self.page_byte_size,
+            &self.serializer,
+        );
+        Ok(())
+    }
+
     pub fn insert(&mut self, entry: Entry<K, V>) -> Result<(), String> {
         match &mut self.root_node {
             None => {
@@ -66,6 +119,53 @@ impl<K: Key + 'static, V: Value + 'static> BPTree<K, V> {
         Ok(())
     }
 
+    pub fn get(&self, key: &K) -> Option<V> {
+        match &self.root_node {
+            None => None,
+            Some(root_node) => {
+                let leaf_node = root_node.leaf_for(key);
+                let value = leaf_node.borrow().get(key);
+                value
+            }
+        }
+    }
+
+    /// Values of every entry with a key in `[low, high]`, in key order
+    pub fn range(&self, low: &K, high: &K) -> Vec<V> {
+        match &self.root_node {
+            None => vec![],
+            Some(root_node) => {
+                let leaf_node = root_node.leaf_for(low);
+                let values = leaf_node.borrow().range(low, high);
+                values
+            }
+        }
+    }
+
+    /// Rebuilds the tree with every node packed as full as it can be
+    pub fn compact(&mut self) {
+        let entries = match &self.root_node {
+            None => return,
+            Some(root_node) => root_node.entries(),
+        };
+        self.root_node =
+            BPTreeNode::bulk_load(entries, self.degree, self.page_byte_size, &self.serializer);
+    }
+
+    /// Removes every entry from the tree
+    pub fn clear(&mut self) {
+        self.root_node = None;
+    }
+
+    /// Number of keys in every node except the root
+    #[allow(dead_code)]
+    fn node_lens(&self) -> Vec<usize> {
+        match &self.root_node {
+            None => vec![],
+            Some(root_node) => root_node.descendant_lens(),
+        }
+    }
+
     /// Returns a depth-first traversal of the keys in the tree.
     ///
     /// Will have duplicates and this function is solely for testing
@@ -81,10 +181,10 @@ impl<K: Key + 'static, V: Value + 'static> BPTree<K, V> {
 
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for BPTree<K, V> {
     type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type IntoIter = LeafIter<K, V>;
     fn into_iter(self) -> Self::IntoIter {
         match self.root_node {
-            None => vec![].into_iter(),
+            None => LeafNode::new().into_iter(),
             Some(root_node) => root_node.into_iter(),
         }
     }
@@ -94,6 +194,21 @@ impl<K: Key + 'static, V: Value + 'static> IntoIterator for BPTree<K, V> {
 mod bptree_test {
     use super::*;
     use pretty_assertions::assert_eq;
+    use serde::Serialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NUM_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    /// Value that counts how many times it has been cloned
+    #[derive(Serialize, Debug, PartialEq, Eq)]
+    struct Counted(i32);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Counted {
+            NUM_CLONES.fetch_add(1, Ordering::SeqCst);
+            Counted(self.0)
+        }
+    }
 
     #[test]
     fn insertion_works() {
@@ -129,4 +244,177 @@ mod bptree_test {
             vec![1, 2, 3, 3, 4, 3, 4, 5, 5, 6, 10, 10, 11, 20, 20, 30]
         );
     }
+
+    #[test]
+    fn clearing_removes_every_entry() {
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock);
+        for key in 1..10 {
+            bptree.insert(Entry::new(key, vec![key])).unwrap();
+        }
+        bptree.clear();
+
+        assert_eq!(bptree.get(&1), None);
+        assert_eq!(
+            bptree.into_iter().collect::<Vec<Vec<i32>>>(),
+            Vec::<Vec<i32>>::new()
+        );
+    }
+
+    #[test]
+    fn entries_can_be_looked_up_by_key() {
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock);
+        for key in &[1, 2, 3, 4, 10, 11, 5, 6, 20, 30] {
+            bptree.insert(Entry::new(*key, vec![*key * 2])).unwrap();
+        }
+
+        for key in &[1, 2, 3, 4, 10, 11, 5, 6, 20, 30] {
+            assert_eq!(bptree.get(key), Some(vec![*key * 2]));
+        }
+        assert_eq!(bptree.get(&7), None);
+        assert_eq!(bptree.get(&31), None);
+    }
+
+    #[test]
+    fn entries_can_be_scanned_by_range() {
+        let mut bptree = BPTree::new(4, 4, Serializer::Mock);
+        for key in &[1, 2, 3, 4, 10, 11, 5, 6, 20, 30] {
+            bptree.insert(Entry::new(*key, vec![*key])).unwrap();
+        }
+
+        assert_eq!(
+            bptree.range(&3, &11),
+            vec![vec![3], vec![4], vec![5], vec![6], vec![10], vec![11]]
+        );
+        assert_eq!(bptree.range(&7, &9), Vec::<Vec<i32>>::new());
+        assert_eq!(bptree.range(&25, &100), vec![vec![30]]);
+    }
+
+    fn assert_nodes_respect_order(order: usize, keys: Vec<i32>) {
+        let mut bptree = BPTree::with_order(order, usize::max_value(), Serializer::Mock).unwrap();
+        for key in &keys {
+            bptree.insert(Entry::new(*key, vec![*key])).unwrap();
+        }
+
+        let node_lens = bptree.node_lens();
+        assert!(node_lens.len() > order, "tree should have split many times");
+        let min = (order + 1) / 2 - 1;
+        for len in node_lens {
+            assert!(
+                min <= len && len <= order - 1,
+                "node with {} keys is outside [{}, {}]",
+                len,
+                min,
+                order - 1
+            );
+        }
+        for key in &keys {
+            assert_eq!(bptree.get(key), Some(vec![*key]));
+        }
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        assert_eq!(
+            bptree.range(&sorted_keys[0], &sorted_keys[sorted_keys.len() - 1]),
+            sorted_keys
+                .iter()
+                .map(|key| vec![*key])
+                .collect::<Vec<Vec<i32>>>()
+        );
+    }
+
+    #[test]
+    fn nodes_respect_the_configured_order() {
+        assert_nodes_respect_order(3, (0..200).collect());
+        assert_nodes_respect_order(3, (0..200).map(|i| i * 7919 % 200).collect());
+        assert_nodes_respect_order(16, (0..2000).collect());
+        assert_nodes_respect_order(16, (0..2000).map(|i| i * 7919 % 2000).collect());
+    }
+
+    #[test]
+    fn order_below_three_is_rejected() {
+        assert!(
+            BPTree::<i32, Vec<i32>>::with_order(2, usize::max_value(), Serializer::Mock).is_err()
+        );
+        assert!(
+            BPTree::<i32, Vec<i32>>::with_order(3, usize::max_value(), Serializer::Mock).is_ok()
+        );
+    }
+
+    #[test]
+    fn bulk_loaded_tree_matches_inserted_tree() {
+        let mut inserted = BPTree::with_order(16, usize::max_value(), Serializer::Mock).unwrap();
+        for key in 0..10000 {
+            inserted.insert(Entry::new(key, vec![key])).unwrap();
+        }
+        let mut bulk_loaded = BPTree::bulk_load(
+            16,
+            usize::max_value(),
+            Serializer::Mock,
+            (0..10000).map(|key| (key, vec![key])),
+        )
+        .unwrap();
+
+        for key in &[0, 1, 15, 16, 4999, 9998, 9999] {
+            assert_eq!(bulk_loaded.get(key), inserted.get(key));
+        }
+        assert_eq!(bulk_loaded.get(&10000), None);
+        assert_eq!(bulk_loaded.range(&0, &9999), inserted.range(&0, &9999));
+        assert_eq!(bulk_loaded.range(&123, &4567), inserted.range(&123, &4567));
+        assert!(bulk_loaded.node_lens().len() < inserted.node_lens().len());
+        for len in bulk_loaded.node_lens() {
+            assert!(7 <= len && len <= 15, "node with {} keys", len);
+        }
+
+        bulk_loaded.insert(Entry::new(10000, vec![10000])).unwrap();
+        assert_eq!(bulk_loaded.get(&10000), Some(vec![10000]));
+    }
+
+    #[test]
+    fn bulk_loading_unsorted_entries_fails() {
+        let result = BPTree::bulk_load(
+            16,
+            usize::max_value(),
+            Serializer::Mock,
+            vec![(1, vec![1]), (3, vec![3]), (2, vec![2])].into_iter(),
+        );
+        assert!(result.is_err());
+
+        let result = BPTree::bulk_load(
+            16,
+            usize::max_value(),
+            Serializer::Mock,
+            vec![(1, vec![1]), (1, vec![1])].into_iter(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn compacting_packs_nodes_full() {
+        let mut bptree = BPTree::with_order(16, usize::max_value(), Serializer::Mock).unwrap();
+        for key in 0..1000 {
+            bptree.insert(Entry::new(key, vec![key])).unwrap();
+        }
+        let num_nodes = bptree.node_lens().len();
+
+        bptree.compact();
+        assert!(bptree.node_lens().len() < num_nodes);
+        assert_eq!(
+            bptree.range(&0, &999),
+            (0..1000).map(|key| vec![key]).collect::<Vec<Vec<i32>>>()
+        );
+    }
+
+    #[test]
+    fn iteration_reads_one_leaf_at_a_time() {
+        let mut bptree = BPTree::with_order(4, usize::max_value(), Serializer::Mock).unwrap();
+        for key in 0..1000 {
+            bptree.insert(Entry::new(key, Counted(key))).unwrap();
+        }
+        NUM_CLONES.store(0, Ordering::SeqCst);
+
+        assert_eq!(
+            bptree.into_iter().take(3).collect::<Vec<Counted>>(),
+            vec![Counted(0), Counted(1), Counted(2)]
+        );
+        assert!(NUM_CLONES.load(Ordering::SeqCst) < 10);
+    }
 }
@@ -1,6 +1,6 @@
 use super::super::super::Serializer;
 use super::Entry;
-use super::{BPTreeNode, InternalNodeEntry};
+use super::{BPTreeNode, InternalNodeEntry, LeafIter};
 use super::{Key, Value};
 use std::cmp::Ordering;
 use std::fmt;
@@ -8,7 +8,7 @@ use std::fmt::Display;
 
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for InternalNodeEntry<K, V> {
     type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type IntoIter = LeafIter<K, V>;
     fn into_iter(self) -> Self::IntoIter {
         self.left.into_iter()
     }
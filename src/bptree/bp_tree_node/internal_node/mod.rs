@@ -1,5 +1,5 @@
 use super::Entry;
-use super::{BPTreeNode, InternalNode, InternalNodeEntry, LeafNode};
+use super::{BPTreeNode, InternalNode, InternalNodeEntry, LeafIter, LeafNode};
 use super::{Key, Value};
 use std::cell::RefCell;
 use std::fmt;
@@ -11,7 +11,7 @@ mod internal_node_entry;
 
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for InternalNode<K, V> {
     type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type IntoIter = LeafIter<K, V>;
     fn into_iter(self) -> Self::IntoIter {
         debug_assert!(!self.entries.is_empty(), "internal node must have entries");
         self.entries[0].clone().into_iter()
@@ -107,8 +107,13 @@ impl<K: Key + 'static, V: Value + 'static> InternalNode<K, V> {
                     Ok(has_node_split_into_two) => match has_node_split_into_two {
                         None => {}
                         Some(split_node) => {
+                            let split_key = split_node.left_key();
+                            if let BPTreeNode::InternalNode(internal_node) = &split_node {
+                                // the split key moves up into this node, same as in from_internals
+                                internal_node.borrow_mut().entries.remove(0);
+                            }
                             let new_internal_node_entry = InternalNodeEntry::new(
-                                split_node.left_key(),
+                                split_key,
                                 self.entries[existing_index].side(&key),
                                 split_node,
                             );
@@ -127,6 +132,16 @@ impl<K: Key + 'static, V: Value + 'static> InternalNode<K, V> {
         Ok(None)
     }
 
+    /// Child node whose keys cover `key`
+    pub fn child_for(&self, key: &K) -> BPTreeNode<K, V> {
+        debug_assert!(!self.entries.is_empty(), "internal node must have entries");
+        let index = match self.entries.binary_search_by(|entry| entry.key.cmp(key)) {
+            Ok(index) => index,
+            Err(index) => index.min(self.entries.len() - 1),
+        };
+        self.entries[index].side(key)
+    }
+
     fn split(&mut self) -> Rc<RefCell<InternalNode<K, V>>> {
         let mid_index = self.entries.len() / 2;
         let right_split = self.entries.split_off(mid_index);
@@ -138,11 +153,8 @@ impl<K: Key + 'static, V: Value + 'static> InternalNode<K, V> {
         let entry_clone = entry.clone();
         self.entries.insert(index, entry);
 
-        match self.entries.get_mut(index - 1) {
-            None => {}
-            Some(left) => {
-                left.right = entry_clone.left.clone();
-            }
+        if index > 0 {
+            self.entries[index - 1].right = entry_clone.left.clone();
         }
         match self.entries.get_mut(index + 1) {
             None => {}
@@ -152,6 +164,21 @@ impl<K: Key + 'static, V: Value + 'static> InternalNode<K, V> {
         }
     }
 
+    /// Child nodes from left to right, where each entry shares its right
+    /// child with the next entry's left child
+    pub fn children(&self) -> Vec<BPTreeNode<K, V>> {
+        let mut children = self
+            .entries
+            .iter()
+            .map(|entry| entry.left.clone())
+            .collect::<Vec<BPTreeNode<K, V>>>();
+        if let Some(last) = self.entries.last() {
+            children.push(last.right.clone());
+        }
+
+        children
+    }
+
     pub fn keys(&self) -> Vec<K> {
         let mut keys = vec![];
         for entry in &self.entries {
@@ -170,7 +197,7 @@ mod internal_node_test {
     macro_rules! new_leaf_node {
         ($page_byte_size:expr, $($key:expr => $value:expr),*) => {{
             let mut leafnode = LeafNode::<i32, Vec<i32>>::new();
-            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock).is_err(), false);)*
+            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, $page_byte_size, Serializer::Mock).is_err(), false);)*
             leafnode
         }};
     }
@@ -237,7 +264,7 @@ mod internal_node_test {
             2 => vec![1, 2, 3],
             3 => vec![1, 2, 3]);
         let right_leafnode = left_leafnode
-            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, Serializer::Mock)
+            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, page_byte_size, Serializer::Mock)
             .unwrap()
             .unwrap();
 
@@ -262,7 +289,7 @@ mod internal_node_test {
             2 => vec![1, 2, 3],
             3 => vec![1, 2, 3]);
         let right_leafnode = left_leafnode
-            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, Serializer::Mock)
+            .insert(Entry::new(4, vec![1, 2, 3]), page_byte_size, page_byte_size, Serializer::Mock)
             .unwrap()
             .unwrap();
 
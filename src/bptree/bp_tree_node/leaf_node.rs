@@ -1,5 +1,5 @@
 use super::Entry;
-use super::LeafNode;
+use super::{LeafIter, LeafNode};
 use super::{Key, Value};
 use std::cell::RefCell;
 use std::fmt;
@@ -30,20 +30,37 @@ impl<K: Key + 'static, V: Value + 'static> Display for LeafNode<K, V> {
 
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for LeafNode<K, V> {
     type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type IntoIter = LeafIter<K, V>;
     fn into_iter(self) -> Self::IntoIter {
-        let mut all_entries = self
-            .entries
-            .iter()
-            .map(|entry| entry.value.clone())
-            .collect::<Vec<V>>();
-        match &self.next {
-            None => {}
-            Some(next_entries) => {
-                all_entries.extend(next_entries.borrow().clone().into_iter());
+        LeafIter {
+            values: self
+                .entries
+                .into_iter()
+                .map(|entry| entry.value)
+                .collect::<Vec<V>>()
+                .into_iter(),
+            next: self.next,
+        }
+    }
+}
+
+impl<K: Key + 'static, V: Value + 'static> Iterator for LeafIter<K, V> {
+    type Item = V;
+    fn next(&mut self) -> Option<V> {
+        loop {
+            if let Some(value) = self.values.next() {
+                return Some(value);
             }
+            let leaf_node = self.next.take()?;
+            let leaf_node = leaf_node.borrow();
+            self.values = leaf_node
+                .entries
+                .iter()
+                .map(|entry| entry.value.clone())
+                .collect::<Vec<V>>()
+                .into_iter();
+            self.next = leaf_node.next.clone();
         }
-        all_entries.into_iter()
     }
 }
 
@@ -66,13 +83,16 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
     pub fn insert(
         &mut self,
         entry: Entry<K, V>,
+        degree: usize,
         page_byte_size: usize,
         serializer: Serializer,
     ) -> Result<Option<Rc<RefCell<LeafNode<K, V>>>>, String> {
         match self.entries.binary_search(&entry) {
             Err(index) => {
                 self.entries.insert(index, entry);
-                if serializer.serialize(&self.entries).len() >= page_byte_size {
+                if self.entries.len() >= degree
+                    || serializer.serialize(&self.entries).len() >= page_byte_size
+                {
                     return Ok(Some(self.split()));
                 }
             }
@@ -107,6 +127,32 @@ impl<K: Key + 'static, V: Value + 'static> LeafNode<K, V> {
         return entries[entries.len() - 1].key.clone();
     }
 
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|index| self.entries[index].value.clone())
+    }
+
+    /// Values of every entry with a key in `[low, high]`, continuing onto the
+    /// next leaves until a key past `high` is found.
+    pub fn range(&self, low: &K, high: &K) -> Vec<V> {
+        let mut values = vec![];
+        for entry in &self.entries {
+            if &entry.key > high {
+                return values;
+            }
+            if &entry.key >= low {
+                values.push(entry.value.clone());
+            }
+        }
+        if let Some(next) = &self.next {
+            values.extend(next.borrow().range(low, high));
+        }
+
+        values
+    }
+
     pub fn keys(&self) -> Vec<K> {
         self.entries
             .iter()
@@ -123,7 +169,7 @@ mod tests {
     macro_rules! new_leaf_node {
         ($page_byte_size:expr, $($key:expr => $value:expr),*) => {{
             let mut leafnode = LeafNode::<i32, Vec<i32>>::new();
-            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, Serializer::Mock).is_err(), false);)*
+            $(assert_eq!(leafnode.insert(Entry::new($key, $value), $page_byte_size, $page_byte_size, Serializer::Mock).is_err(), false);)*
             leafnode
         }};
     }
@@ -200,7 +246,7 @@ mod tests {
             1 => vec![1,2,3], 
             3 => vec![400, 500, 600]);
         assert_eq!(
-            leafnode.insert(Entry::new(3, vec![-1, -2, -3]), page_byte_size, Serializer::Mock).is_err(),
+            leafnode.insert(Entry::new(3, vec![-1, -2, -3]), page_byte_size, page_byte_size, Serializer::Mock).is_err(),
             true
         );
     }
@@ -19,7 +19,7 @@ pub enum BPTreeNode<K: Key, V: Value> {
 
 impl<K: Key + 'static, V: Value + 'static> IntoIterator for BPTreeNode<K, V> {
     type Item = V;
-    type IntoIter = ::std::vec::IntoIter<Self::Item>;
+    type IntoIter = LeafIter<K, V>;
     fn into_iter(self) -> Self::IntoIter {
         match &self {
             BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().clone().into_iter(),
@@ -48,7 +48,7 @@ impl<K: Key + 'static, V: Value + 'static> BPTreeNode<K, V> {
         match &self {
             BPTreeNode::LeafNode(leaf_node) => leaf_node
                 .borrow_mut()
-                .insert(entry, page_byte_size, serializer)
+                .insert(entry, degree, page_byte_size, serializer)
                 .map(|opt| opt.map(|rc| BPTreeNode::LeafNode(rc))),
             BPTreeNode::InternalNode(internal_node) => {
                 internal_node
@@ -58,6 +58,124 @@ impl<K: Key + 'static, V: Value + 'static> BPTreeNode<K, V> {
         }
     }
 
+    /// Builds a tree bottom-up from entries sorted by key, packing every node
+    /// as full as it can be without splitting
+    pub fn bulk_load(
+        entries: Vec<Entry<K, V>>,
+        degree: usize,
+        page_byte_size: usize,
+        serializer: &super::Serializer,
+    ) -> Option<BPTreeNode<K, V>> {
+        let mut leaf_entries: Vec<Vec<Entry<K, V>>> = vec![];
+        let mut current = vec![];
+        for entry in entries {
+            current.push(entry);
+            if current.len() > 1
+                && (current.len() >= degree
+                    || serializer.serialize(&current).len() >= page_byte_size)
+            {
+                let last = current.pop().unwrap();
+                leaf_entries.push(current);
+                current = vec![last];
+            }
+        }
+        if !current.is_empty() {
+            leaf_entries.push(current);
+        }
+        // even out the last two leaves so the last one isn't left nearly empty
+        let num_leaves = leaf_entries.len();
+        if num_leaves > 1
+            && leaf_entries[num_leaves - 1].len() < leaf_entries[num_leaves - 2].len() / 2
+        {
+            let last = leaf_entries.pop().unwrap();
+            let mut left = leaf_entries.pop().unwrap();
+            left.extend(last);
+            let right = left.split_off(left.len() / 2);
+            leaf_entries.push(left);
+            leaf_entries.push(right);
+        }
+
+        let mut next = None;
+        let mut nodes = vec![];
+        for entries in leaf_entries.into_iter().rev() {
+            let key = entries[0].key.clone();
+            let leaf_node = Rc::new(RefCell::new(LeafNode { entries, next }));
+            next = Some(leaf_node.clone());
+            nodes.push((key, BPTreeNode::LeafNode(leaf_node)));
+        }
+        nodes.reverse();
+
+        while nodes.len() > 1 {
+            let mut groups = vec![];
+            while !nodes.is_empty() {
+                let rest = nodes.split_off(degree.min(nodes.len()));
+                groups.push(nodes);
+                nodes = rest;
+            }
+            // an internal node needs at least two children
+            let num_groups = groups.len();
+            if num_groups > 1 && groups[num_groups - 1].len() == 1 {
+                let child = groups[num_groups - 2].pop().unwrap();
+                groups[num_groups - 1].insert(0, child);
+            }
+
+            nodes = groups
+                .into_iter()
+                .map(|children| {
+                    let entries = children
+                        .windows(2)
+                        .map(|pair| {
+                            InternalNodeEntry::new(
+                                pair[1].0.clone(),
+                                pair[0].1.clone(),
+                                pair[1].1.clone(),
+                            )
+                        })
+                        .collect();
+                    let key = children[0].0.clone();
+                    (
+                        key,
+                        BPTreeNode::InternalNode(Rc::new(RefCell::new(InternalNode { entries }))),
+                    )
+                })
+                .collect();
+        }
+
+        nodes.pop().map(|(_, node)| node)
+    }
+
+    /// Every entry below this node in key order
+    pub fn entries(&self) -> Vec<Entry<K, V>> {
+        let mut leaf_node = match &self {
+            BPTreeNode::LeafNode(leaf_node) => leaf_node.clone(),
+            BPTreeNode::InternalNode(internal_node) => {
+                let first_child = internal_node.borrow().entries[0].left.clone();
+                return first_child.entries();
+            }
+        };
+
+        let mut entries = vec![];
+        loop {
+            entries.extend(leaf_node.borrow().entries.iter().cloned());
+            let next = leaf_node.borrow().next.clone();
+            match next {
+                None => return entries,
+                Some(next) => leaf_node = next,
+            }
+        }
+    }
+
+    /// Leaf node that `key` is or would be stored in
+    pub fn leaf_for(&self, key: &K) -> Rc<RefCell<LeafNode<K, V>>> {
+        match &self {
+            BPTreeNode::LeafNode(leaf_node) => leaf_node.clone(),
+            BPTreeNode::InternalNode(internal_node) => {
+                let child = internal_node.borrow().child_for(key);
+                child.leaf_for(key)
+            }
+        }
+    }
+
     fn left_key(&self) -> K {
         match &self {
             BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().left_key(),
@@ -79,6 +197,23 @@ impl<K: Key + 'static, V: Value + 'static> BPTreeNode<K, V> {
         }
     }
 
+    /// Number of keys in each node below this one
+    #[allow(dead_code)]
+    pub fn descendant_lens(&self) -> Vec<usize> {
+        match &self {
+            BPTreeNode::LeafNode(_) => vec![],
+            BPTreeNode::InternalNode(internal_node) => {
+                let mut lens = vec![];
+                for child in internal_node.borrow().children() {
+                    lens.push(child.len());
+                    lens.extend(child.descendant_lens());
+                }
+
+                lens
+            }
+        }
+    }
+
     pub fn keys(&self) -> Vec<K> {
         match &self {
             BPTreeNode::LeafNode(leaf_node) => leaf_node.borrow().keys(),
@@ -93,6 +228,14 @@ pub struct LeafNode<K: Key, V: Value> {
     next: Option<Rc<RefCell<LeafNode<K, V>>>>,
 }
 
+/// Values of a leaf node and of the leaves after it, in key order. A leaf is
+/// only read once the values of the leaves before it are used up, so
+/// stopping early never reads the rest of the tree.
+pub struct LeafIter<K: Key, V: Value> {
+    values: ::std::vec::IntoIter<V>,
+    next: Option<Rc<RefCell<LeafNode<K, V>>>>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Eq, PartialEq)]
 struct InternalNodeEntry<K: Key, V: Value> {
     key: K,
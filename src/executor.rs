@@ -1,5 +1,18 @@
-use crate::ast::{ColumnSet, Value};
-use std::collections::HashMap;
+use crate::ast::{
+    self, Alteration, Ast, Collation, ColumnSet, DataType, Expression, ForeignKeyAction,
+    NullsOrder, Operator, OrderingTerm, Predicate, SchemaChange, SortOrder, TableSchema, Value,
+};
+use crate::parser;
+use crate::result_set::ResultSet;
+use crate::row::FromRow;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::rc::Rc;
+use std::time::Instant;
 
 #[cfg(test)]
 extern crate mockers_derive;
@@ -9,8 +22,18 @@ use mockers_derive::mocked;
 
 pub trait Column {
     fn name(&self) -> &String;
+    /// Position of the column within a row
+    fn index(&self) -> usize;
 }
 
+/// A table that rows can be inserted into and selected from.
+///
+/// Tables are not thread safe. The B+ tree that stores the rows shares its
+/// nodes through `Rc<RefCell<_>>`, so a table is neither `Send` nor `Sync`.
+/// Instead, exclusive access is enforced by the borrow checker: mutations go
+/// through `&mut self` and reads through `&self`, so any number of reads may
+/// be in flight at once but never alongside a write. Callers that need to
+/// share an `Executor` across threads must wrap it in their own lock.
 #[cfg_attr(test, mocked)]
 pub trait Table {
     /// Name of the table
@@ -23,13 +46,45 @@ pub trait Table {
         &mut self,
         row: HashMap<String, Value>,
     ) -> Result<&mut dyn Table, String>;
+    /// Inserts rows with every column given, in column order, all at once,
+    /// and returns them as they were stored, including their assigned
+    /// rowids. Either every row is inserted or none are.
+    fn insert_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, String>;
+    /// Removes every row at once rather than one at a time
+    fn truncate(&mut self);
+    fn add_column(&mut self, column: ast::Column, default: Value) -> Result<(), String>;
+    /// Replaces the columns and rows of the table
+    fn restore(&mut self, schema: TableSchema, rows: Vec<Vec<Value>>) -> Result<(), String>;
+    /// Reclaims the space wasted by partially filled storage
+    fn vacuum(&mut self);
     fn row_len(&self) -> usize;
+    /// Number of rows, kept up to date as rows are inserted and removed
+    fn row_count(&self) -> usize;
+    /// The row most recently inserted, including its assigned rowid
+    fn last_inserted_row(&self) -> Option<Vec<Value>>;
+    /// Every row in ascending primary key order, or in ascending order of
+    /// the first column if the table has no primary key. The order does not
+    /// depend on the order the rows were inserted in. Rows are read as the
+    /// iterator is advanced, so stopping early skips the rest of the table.
     fn select_rows(&self) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String>;
-    fn select_rows_with_named_columns(
+    /// Row with the primary key `key`, which holds a value for each primary
+    /// key column in key order, without scanning the table
+    fn get(&self, key: &Vec<Value>) -> Result<Option<Vec<Value>>, String>;
+    fn select_rows_by_primary_keys(
         &self,
-        column_names: &Vec<String>,
+        keys: &Vec<Value>,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String>;
+    fn select_rows_in_primary_key_range(
+        &self,
+        low: &Value,
+        high: &Value,
     ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String>;
     fn columns(&self) -> Vec<Box<dyn Column>>;
+    /// Schema of the table with its columns in declaration order
+    fn schema(&self) -> TableSchema;
+    /// Name of the primary key column that rows are indexed by, unless the
+    /// primary key is made up of several columns
+    fn primary_key(&self) -> Option<String>;
 }
 
 pub trait Insertion {
@@ -37,61 +92,444 @@ pub trait Insertion {
     fn validate(&self) -> Result<(), String>;
     fn column_names(&self) -> Option<Box<dyn Iterator<Item = String>>>;
     fn values(&self) -> Box<dyn Iterator<Item = Value>>;
+    /// Columns of the inserted row to return
+    fn returning(&self) -> Option<ColumnSet>;
 }
 
 pub trait Selection {
     fn table_name(&self) -> &String;
     fn validate(&self) -> Result<(), String>;
     fn columns(&self) -> ColumnSet;
+    fn predicate(&self) -> Option<Predicate>;
+    /// Keys the selected rows are sorted by, most significant first
+    fn order_by(&self) -> Vec<OrderingTerm>;
+}
+
+/// How the rows matching a predicate are looked up
+enum Access {
+    Scan,
+    PrimaryKeys(Vec<Value>),
+    /// Inclusive on both ends
+    PrimaryKeyRange(Value, Value),
+    /// Value of every column of a composite primary key, in key order
+    Key(Vec<Value>),
+}
+
+/// Result of a statement run with `Executor::execute`
+#[derive(Debug, PartialEq)]
+pub enum ExecOutcome {
+    RowsAffected(usize),
+    Rows(Vec<Vec<Value>>),
+    Done,
+}
+
+/// Limits on the work a statement may do before it is aborted. Nothing is
+/// limited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecOptions {
+    /// Most rows a statement may read, including the rows it filters out
+    pub max_scanned_rows: Option<usize>,
+    /// Time by which a statement must be done
+    pub deadline: Option<Instant>,
+}
+
+/// Whether a logged row was added to or removed from its table
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MutationKind {
+    Insert,
+    Delete,
+}
+
+/// Row inserted into or deleted from a table, as recorded in the mutation
+/// log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Mutation {
+    pub kind: MutationKind,
+    pub table_name: String,
+    /// Values of the key columns of the row, which identify it in its table
+    pub key: Vec<Value>,
+    pub row: Vec<Value>,
+}
+
+/// How NULL compares with other values when rows are sorted by an ORDER BY
+/// key that doesn't say where NULLs go. Comparisons in predicates are never
+/// affected because comparing NULL is always unknown, and neither are
+/// primary key order and ranges, which always treat NULL as the greatest
+/// value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonMode {
+    /// NULL is less than every other value, like SQLite, so NULLs come first
+    /// in ascending order and last in descending order
+    Sqlite,
+    /// NULL is greater than every other value, like `Value`'s own order, so
+    /// NULLs come last in ascending order and first in descending order
+    NullsGreatest,
+}
+
+impl Default for ComparisonMode {
+    fn default() -> ComparisonMode {
+        ComparisonMode::Sqlite
+    }
+}
+
+impl ComparisonMode {
+    /// Where NULLs go when sorting in `order`
+    fn nulls_order(&self, order: SortOrder) -> NullsOrder {
+        match (self, order) {
+            (ComparisonMode::Sqlite, SortOrder::Asc)
+            | (ComparisonMode::NullsGreatest, SortOrder::Desc) => NullsOrder::First,
+            (ComparisonMode::Sqlite, SortOrder::Desc)
+            | (ComparisonMode::NullsGreatest, SortOrder::Asc) => NullsOrder::Last,
+        }
+    }
+}
+
+/// Rows removed from a table because its foreign key cascades, along with
+/// the rows it keeps
+struct CascadedDelete {
+    table_name: String,
+    kept_rows: Vec<Vec<Value>>,
+    removed_rows: Vec<Vec<Value>>,
+}
+
+/// Schema and rows of a table before it was changed
+struct SavedTable {
+    schema: TableSchema,
+    rows: Vec<Vec<Value>>,
+}
+
+/// Tables that existed when a savepoint was started, along with how each
+/// table changed since then was before its first change
+struct Savepoint {
+    name: String,
+    table_names: HashSet<String>,
+    tables: HashMap<String, Rc<SavedTable>>,
+    /// Number of mutations logged before the savepoint was started
+    mutation_log_len: usize,
 }
 
+/// Check run on every row before it is inserted into a table, which is given
+/// the values of the row in column order and rejects it with an error
+pub type InsertHook = Box<dyn Fn(&[Value]) -> Result<(), String>>;
+
 pub struct Executor<T: Table> {
     tables: HashMap<String, T>,
+    /// Open savepoints, innermost last
+    savepoints: Vec<Savepoint>,
+    /// Whether every statement that would change a table is rejected
+    read_only: bool,
+    /// Hooks of each table, in the order they were added
+    before_insert_hooks: HashMap<String, Vec<InsertHook>>,
+    comparison_mode: ComparisonMode,
+    /// Every row inserted or deleted, oldest first, if mutations are being
+    /// logged
+    mutation_log: Option<Vec<Mutation>>,
 }
 
 impl<T: Table> Executor<T> {
     pub fn new() -> Executor<T> {
         return Executor {
             tables: HashMap::new(),
+            savepoints: vec![],
+            read_only: false,
+            before_insert_hooks: HashMap::new(),
+            comparison_mode: ComparisonMode::default(),
+            mutation_log: None,
+        };
+    }
+
+    /// Makes every later attempt to create, change or vacuum a table fail,
+    /// or allows it again. Selections and introspection are unaffected.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Changes where ORDER BY puts NULLs when it isn't told
+    pub fn set_comparison_mode(&mut self, comparison_mode: ComparisonMode) {
+        self.comparison_mode = comparison_mode;
+    }
+
+    pub fn comparison_mode(&self) -> ComparisonMode {
+        self.comparison_mode
+    }
+
+    /// Starts or stops logging every row that is inserted or deleted.
+    /// Stopping discards the log.
+    pub fn set_mutation_logging(&mut self, enabled: bool) {
+        self.mutation_log = match enabled {
+            true => Some(self.mutation_log.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// Mutations logged since logging started or the log was last cleared,
+    /// in the order they were made. Mutations undone by rolling back to a
+    /// savepoint are removed from the log.
+    ///
+    /// Every mutation goes through `&mut self`, so entries from different
+    /// threads can't interleave; sharing an executor across threads already
+    /// requires a lock around it.
+    pub fn mutation_log_iter(&self) -> impl Iterator<Item = &Mutation> {
+        self.mutation_log.iter().flatten()
+    }
+
+    /// Empties the mutation log without stopping logging
+    pub fn clear_mutation_log(&mut self) {
+        if let Some(mutation_log) = &mut self.mutation_log {
+            mutation_log.clear();
+        }
+        for savepoint in &mut self.savepoints {
+            savepoint.mutation_log_len = 0;
+        }
+    }
+
+    /// Logs rows inserted into or deleted from `table_name` if mutations are
+    /// being logged
+    fn log_mutations(&mut self, kind: MutationKind, table_name: &str, rows: Vec<Vec<Value>>) {
+        let mutation_log = match &mut self.mutation_log {
+            None => return,
+            Some(mutation_log) => mutation_log,
         };
+        let table = &self.tables[table_name];
+        let column_indices = Self::column_indices(table);
+        let key_indices = table
+            .schema()
+            .key_columns()
+            .iter()
+            .map(|column| column_indices[column])
+            .collect::<Vec<usize>>();
+        for row in rows {
+            mutation_log.push(Mutation {
+                kind,
+                table_name: table_name.to_string(),
+                key: project(&row, &key_indices),
+                row,
+            });
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), String> {
+        if self.read_only {
+            return Err("attempt to write a readonly database".to_string());
+        }
+        Ok(())
     }
 
     pub fn add_table(&mut self, table: T) -> Result<(), String> {
+        self.check_writable()?;
         let table_name = table.name();
         if self.table_exists(&table_name) {
             return Err(format!("table {} already exists", &table_name).to_string());
         }
+        let schema = table.schema();
+        for foreign_key in &schema.foreign_keys {
+            let parent_schema = if foreign_key.parent_table == table_name {
+                schema.clone()
+            } else {
+                match self.tables.get(&foreign_key.parent_table) {
+                    None => return Err(format!("no such table: {}", foreign_key.parent_table)),
+                    Some(parent) => parent.schema(),
+                }
+            };
+            if foreign_key.parent_columns != parent_schema.key_columns() {
+                return Err(format!(
+                    "foreign key mismatch - \"{}\" referencing \"{}\"",
+                    table_name, foreign_key.parent_table
+                ));
+            }
+        }
         self.tables.insert(table_name.to_string(), table);
         Ok(())
     }
 
-    pub fn insert<I: Insertion>(&mut self, insertion: I) -> Result<(), String> {
-        let table_name = insertion.table_name();
+    /// Adds a hook that is run before every row is inserted into the table,
+    /// after the hooks added before it. The first hook to fail rejects the
+    /// row with its error.
+    ///
+    /// Hooks are added through `&mut self`, so like every other change they
+    /// can't race with an insert.
+    pub fn add_before_insert_hook(
+        &mut self,
+        table_name: &str,
+        hook: InsertHook,
+    ) -> Result<(), String> {
         if !self.table_exists(table_name) {
             return Err(format!("no such table: {}", table_name));
         }
-        let result = insertion.validate();
-        if result.is_err() {
-            return result;
+        self.before_insert_hooks
+            .entry(table_name.to_string())
+            .or_insert_with(Vec::new)
+            .push(hook);
+        Ok(())
+    }
+
+    fn run_before_insert_hooks(
+        &self,
+        schema: &TableSchema,
+        row: &HashMap<String, Value>,
+    ) -> Result<(), String> {
+        if let Some(hooks) = self.before_insert_hooks.get(&schema.name) {
+            let values = row_values(schema, row);
+            for hook in hooks {
+                hook(&values)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts a row and returns the columns of it that were asked for with
+    /// RETURNING, if any
+    pub fn insert<I: Insertion>(&mut self, insertion: I) -> Result<Vec<Vec<Value>>, String> {
+        self.check_writable()?;
+        let table_name = insertion.table_name();
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
         }
+        insertion.validate()?;
+        let schema = self.tables[table_name].schema();
+        let row: HashMap<String, Value> = match insertion.column_names() {
+            None => schema
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .zip(insertion.values())
+                .collect(),
+            Some(column_names) => {
+                let mut row = HashMap::new();
+                for (column_name, value) in column_names.zip(insertion.values()) {
+                    if !schema
+                        .columns
+                        .iter()
+                        .any(|column| column.name == column_name)
+                    {
+                        return Err(format!("no such column: {}", column_name));
+                    }
+                    if row.contains_key(&column_name) {
+                        return Err(format!("duplicate column name: {}", column_name));
+                    }
+                    row.insert(column_name, value);
+                }
+                row
+            }
+        };
+        check_bound(&row)?;
+        self.run_before_insert_hooks(&schema, &row)?;
+        check_constraints(&schema, &row)?;
+        self.check_foreign_keys(&schema, &row)?;
+
+        self.save_before_change(table_name)?;
         let table = self.tables.get_mut(table_name).unwrap();
-        let values = insertion.values();
+        let returning = match insertion.returning() {
+            None => None,
+            Some(columns) => Some(Self::projection(table, &columns)?),
+        };
 
         if insertion.column_names().is_none() {
-            let result = table.insert_row(values.collect());
-            if result.is_err() {
-                return result.and_then(|_| Ok(()));
-            }
+            table.insert_row(insertion.values().collect())?;
         } else {
-            let column_names = insertion.column_names().unwrap();
-            let mut row = HashMap::new();
-            for kv in column_names.zip(values) {
-                let (column_name, value) = kv;
-                row.insert(column_name.clone(), value.clone());
+            table.insert_row_with_named_columns(row)?;
+        }
+
+        if returning.is_none() && self.mutation_log.is_none() {
+            return Ok(vec![]);
+        }
+        let row = table.last_inserted_row().unwrap();
+        let returned = match returning {
+            None => vec![],
+            Some(None) => vec![row.clone()],
+            Some(Some(expressions)) => vec![evaluate_expressions(
+                &expressions,
+                &row,
+                &Self::column_indices(table),
+            )],
+        };
+        self.log_mutations(MutationKind::Insert, table_name, vec![row]);
+        Ok(returned)
+    }
+
+    /// Inserts rows with a value for every column, in column order, and
+    /// returns how many there were.
+    ///
+    /// Unlike calling `insert` once per row, the table and its schema are
+    /// looked up once for the whole batch and the rows are handed to the
+    /// table together, which loads them straight into its tree when it is
+    /// empty. Every row is checked before any is inserted, so either every
+    /// row is inserted or, if one of them breaks a constraint, none are.
+    pub fn insert_many<I: Iterator<Item = Vec<Value>>>(
+        &mut self,
+        table_name: &str,
+        rows: I,
+    ) -> Result<usize, String> {
+        self.check_writable()?;
+        let schema = match self.tables.get(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(table) => table.schema(),
+        };
+
+        let rows = rows.collect::<Vec<Vec<Value>>>();
+        for row in &rows {
+            if row.len() != schema.columns.len() {
+                return Err(format!(
+                    "table {} has {} columns but {} values were supplied",
+                    table_name,
+                    schema.columns.len(),
+                    row.len()
+                ));
+            }
+            let row = schema
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .zip(row.iter().cloned())
+                .collect::<HashMap<String, Value>>();
+            check_bound(&row)?;
+            self.run_before_insert_hooks(&schema, &row)?;
+            check_constraints(&schema, &row)?;
+            self.check_foreign_keys(&schema, &row)?;
+        }
+
+        self.save_before_change(table_name)?;
+        let inserted = self.tables.get_mut(table_name).unwrap().insert_rows(rows)?;
+        let num_rows = inserted.len();
+        self.log_mutations(MutationKind::Insert, table_name, inserted);
+        Ok(num_rows)
+    }
+
+    /// Checks that every foreign key of a row to be inserted into the table
+    /// with `schema` refers to an existing row. A key with a NULL in it
+    /// refers to nothing and always passes.
+    fn check_foreign_keys(
+        &self,
+        schema: &TableSchema,
+        row: &HashMap<String, Value>,
+    ) -> Result<(), String> {
+        for foreign_key in &schema.foreign_keys {
+            let key = foreign_key
+                .columns
+                .iter()
+                .map(|column| row.get(column).cloned().unwrap_or(Value::Null))
+                .collect::<Vec<Value>>();
+            if key.contains(&Value::Null) {
+                continue;
+            }
+            let own_key = foreign_key
+                .parent_columns
+                .iter()
+                .map(|column| row.get(column).cloned().unwrap_or(Value::Null))
+                .collect::<Vec<Value>>();
+            if foreign_key.parent_table == schema.name && key == own_key {
+                continue;
             }
-            let result = table.insert_row_with_named_columns(row);
-            if result.is_err() {
-                return result.map(|_| ());
+            let parent = match self.tables.get(&foreign_key.parent_table) {
+                None => return Err(format!("no such table: {}", foreign_key.parent_table)),
+                Some(parent) => parent,
+            };
+            if parent.get(&key)?.is_none() {
+                return Err("FOREIGN KEY constraint failed".to_string());
             }
         }
         Ok(())
@@ -100,6 +538,35 @@ impl<T: Table> Executor<T> {
     pub fn select<S: Selection>(
         &self,
         selection: S,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+        self.select_within(selection, ExecOptions::default(), Rc::new(Cell::new(false)))
+    }
+
+    /// Selects rows like `select` but gives up as soon as reading them takes
+    /// more work than `options` allow
+    pub fn select_with_options<S: Selection>(
+        &self,
+        selection: S,
+        options: ExecOptions,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        let exceeded = Rc::new(Cell::new(false));
+        let rows = self
+            .select_within(selection, options, exceeded.clone())?
+            .collect();
+        if exceeded.get() {
+            return Err("query budget exceeded".to_string());
+        }
+
+        Ok(rows)
+    }
+
+    /// Selects rows, stopping the scan and setting `exceeded` once `options`
+    /// are exceeded
+    fn select_within<S: Selection>(
+        &self,
+        selection: S,
+        options: ExecOptions,
+        exceeded: Rc<Cell<bool>>,
     ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
         let table_name = selection.table_name();
         if !self.table_exists(table_name) {
@@ -107,48 +574,4439 @@ impl<T: Table> Executor<T> {
         }
 
         let table = self.tables.get(table_name).unwrap();
-        let column_set = &selection.columns();
-        match column_set {
-            ColumnSet::WildCard => table.select_rows(),
-            ColumnSet::Names(column_names) => table.select_rows_with_named_columns(&column_names),
+        let count_only = selection.columns() == ColumnSet::Count;
+        if count_only && selection.predicate().is_none() {
+            let count = Value::Integer(table.row_count() as i64);
+            return Ok(Box::new(std::iter::once(vec![count])));
+        }
+
+        let column_indices = Self::column_indices(table);
+        let collations = Self::collations(table);
+        let projection = Self::projection(table, &selection.columns())?;
+        let mut sort_keys = vec![];
+        for term in selection.order_by() {
+            let nulls = term
+                .nulls
+                .unwrap_or_else(|| self.comparison_mode.nulls_order(term.order));
+            match column_indices.get(&term.column) {
+                None => return Err(format!("no such column: {}", term.column)),
+                Some(index) => {
+                    let collation = term.collation.unwrap_or(collations[*index]);
+                    sort_keys.push((*index, term.order, nulls, collation))
+                }
+            }
+        }
+
+        let rows: Box<dyn Iterator<Item = Vec<Value>>> = match selection.predicate() {
+            None => limit(table.select_rows()?, options, exceeded),
+            Some(predicate) => {
+                let predicate = self.run_subqueries(
+                    unqualify(&predicate, table_name),
+                    table_name,
+                    &column_indices,
+                    options,
+                    &exceeded,
+                )?;
+                for column in predicate.columns() {
+                    if !column_indices.contains_key(column) {
+                        return Err(format!("no such column: {}", column));
+                    }
+                }
+                let rows = limit(Self::scan(table, &predicate)?, options, exceeded.clone());
+                if predicate.has_subquery() {
+                    // the subqueries left depend on the row being filtered
+                    let mut matching_rows = vec![];
+                    for row in rows {
+                        let row_predicate = self.run_correlated_subqueries(
+                            &predicate,
+                            table_name,
+                            &column_indices,
+                            &row,
+                            options,
+                            &exceeded,
+                        )?;
+                        if evaluate(&row_predicate, &row, &column_indices, &collations)
+                            == Some(true)
+                        {
+                            matching_rows.push(row);
+                        }
+                    }
+                    Box::new(matching_rows.into_iter())
+                } else {
+                    Box::new(rows.filter(move |row| {
+                        evaluate(&predicate, row, &column_indices, &collations) == Some(true)
+                    }))
+                }
+            }
+        };
+
+        let rows: Box<dyn Iterator<Item = Vec<Value>>> = if sort_keys.is_empty() {
+            rows
+        } else {
+            let mut rows = rows.collect::<Vec<Vec<Value>>>();
+            rows.sort_by(|a, b| compare_rows(a, b, &sort_keys));
+            Box::new(rows.into_iter())
+        };
+
+        if count_only {
+            let count = Value::Integer(rows.count() as i64);
+            return Ok(Box::new(std::iter::once(vec![count])));
+        }
+
+        match projection {
+            None => Ok(rows),
+            Some(expressions) => {
+                let column_indices = Self::column_indices(table);
+                Ok(Box::new(rows.map(move |row| {
+                    evaluate_expressions(&expressions, &row, &column_indices)
+                })))
+            }
         }
     }
 
-    fn table_exists(&self, table_name: &str) -> bool {
-        return self.tables.get(table_name).is_some();
+    /// Runs the subqueries of `predicate`, a predicate on `table_name`, and
+    /// replaces each of them with the values it selected. An EXISTS that
+    /// refers to the columns of `table_name` is left to be run for each row.
+    fn run_subqueries(
+        &self,
+        predicate: Predicate,
+        table_name: &str,
+        column_indices: &HashMap<String, usize>,
+        options: ExecOptions,
+        exceeded: &Rc<Cell<bool>>,
+    ) -> Result<Predicate, String> {
+        let run = |predicate| {
+            self.run_subqueries(predicate, table_name, column_indices, options, exceeded)
+        };
+        Ok(match predicate {
+            Predicate::InSubquery(column, selection) => {
+                Predicate::In(column, self.subquery(*selection, options, exceeded)?)
+            }
+            Predicate::ComparisonSubquery(column, operator, selection) => {
+                let mut values = self.subquery(*selection, options, exceeded)?;
+                if values.len() > 1 {
+                    return Err("scalar subquery returned more than one row".to_string());
+                }
+                Predicate::Comparison(column, operator, values.pop().unwrap_or(Value::Null))
+            }
+            Predicate::Exists(selection) => {
+                if self.is_correlated(&selection, table_name, column_indices) {
+                    Predicate::Exists(selection)
+                } else {
+                    Predicate::Constant(self.exists(*selection, options, exceeded)?)
+                }
+            }
+            Predicate::And(left, right) => {
+                Predicate::And(Box::new(run(*left)?), Box::new(run(*right)?))
+            }
+            Predicate::Or(left, right) => {
+                Predicate::Or(Box::new(run(*left)?), Box::new(run(*right)?))
+            }
+            Predicate::Not(predicate) => Predicate::Not(Box::new(run(*predicate)?)),
+            predicate => predicate,
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ast;
-    use mockers::Scenario;
-    use std::collections::HashMap;
+    /// Runs every EXISTS of `predicate` with the values of `row`, a row of
+    /// `table_name`, in place of the columns of `table_name` it refers to
+    fn run_correlated_subqueries(
+        &self,
+        predicate: &Predicate,
+        table_name: &str,
+        column_indices: &HashMap<String, usize>,
+        row: &[Value],
+        options: ExecOptions,
+        exceeded: &Rc<Cell<bool>>,
+    ) -> Result<Predicate, String> {
+        let run = |predicate| {
+            self.run_correlated_subqueries(
+                predicate,
+                table_name,
+                column_indices,
+                row,
+                options,
+                exceeded,
+            )
+        };
+        Ok(match predicate {
+            Predicate::Exists(selection) => {
+                let selection = self.correlate(selection, table_name, column_indices, row)?;
+                Predicate::Constant(self.exists(selection, options, exceeded)?)
+            }
+            Predicate::And(left, right) => {
+                Predicate::And(Box::new(run(left)?), Box::new(run(right)?))
+            }
+            Predicate::Or(left, right) => {
+                Predicate::Or(Box::new(run(left)?), Box::new(run(right)?))
+            }
+            Predicate::Not(predicate) => Predicate::Not(Box::new(run(predicate)?)),
+            predicate => predicate.clone(),
+        })
+    }
 
-    #[test]
-    fn should_fail_to_create_a_table_if_one_with_same_name_already_exists() {
-        let scenario = Scenario::new();
-        let table_name = "apples".to_string();
-        let (table, _) = scenario.create_mock_for::<dyn Table>();
-        let (table2, table2_handle) = scenario.create_mock_for::<dyn Table>();
-        scenario.expect(table2_handle.name().and_return("apples".to_string()));
-        let mut tables: HashMap<String, TableMock> = HashMap::new();
-        tables.insert(table_name, table);
+    /// Whether the selection has any rows, which is known as soon as the
+    /// first one is found
+    fn exists(
+        &self,
+        selection: ast::Selection,
+        options: ExecOptions,
+        exceeded: &Rc<Cell<bool>>,
+    ) -> Result<bool, String> {
+        Ok(self
+            .select_within(selection, options, exceeded.clone())?
+            .next()
+            .is_some())
+    }
 
-        let mut executor = Executor { tables };
-        let result = executor.add_table(table2);
-        assert_eq!(result.is_err(), true);
+    /// Works out whether `column` of a subquery's predicate is a column of
+    /// the subquery's table or of `outer_table`, the table of the query
+    /// around it. Unqualified columns belong to the subquery's table if it
+    /// has them.
+    fn resolve_column(
+        &self,
+        column: &str,
+        selection: &ast::Selection,
+        outer_table: &str,
+        outer_columns: &HashMap<String, usize>,
+    ) -> ColumnRef {
+        let inner_table = Selection::table_name(selection);
+        match column.find('.') {
+            Some(dot) => {
+                let (qualifier, name) = (&column[..dot], &column[dot + 1..]);
+                if qualifier.eq_ignore_ascii_case(inner_table) {
+                    ColumnRef::Inner(name.to_string())
+                } else if qualifier.eq_ignore_ascii_case(outer_table) {
+                    ColumnRef::Outer(name.to_string())
+                } else {
+                    ColumnRef::Inner(column.to_string())
+                }
+            }
+            None => {
+                let is_inner = match self.tables.get(inner_table) {
+                    None => true,
+                    Some(table) => Self::column_indices(table).contains_key(column),
+                };
+                if !is_inner && outer_columns.contains_key(column) {
+                    ColumnRef::Outer(column.to_string())
+                } else {
+                    ColumnRef::Inner(column.to_string())
+                }
+            }
+        }
     }
 
-    #[test]
-    fn should_fail_to_insert_row_if_table_does_not_exist() {
-        let table_name = "oranges".to_string();
-        let mut executor = Executor::<TableMock> {
-            tables: HashMap::new(),
+    fn is_correlated(
+        &self,
+        selection: &ast::Selection,
+        outer_table: &str,
+        outer_columns: &HashMap<String, usize>,
+    ) -> bool {
+        match Selection::predicate(selection) {
+            None => false,
+            Some(predicate) => predicate.columns().into_iter().any(|column| {
+                match self.resolve_column(column, selection, outer_table, outer_columns) {
+                    ColumnRef::Inner(_) => false,
+                    ColumnRef::Outer(_) => true,
+                }
+            }),
+        }
+    }
+
+    /// Selection with the columns of `outer_table` that its predicate refers
+    /// to replaced by their values in `outer_row`
+    fn correlate(
+        &self,
+        selection: &ast::Selection,
+        outer_table: &str,
+        outer_columns: &HashMap<String, usize>,
+        outer_row: &[Value],
+    ) -> Result<ast::Selection, String> {
+        let resolve =
+            |column: &str| self.resolve_column(column, selection, outer_table, outer_columns);
+        let outer_value = |column: &str| match outer_columns.get(column) {
+            None => Err(format!("no such column: {}.{}", outer_table, column)),
+            Some(index) => Ok(outer_row[*index].clone()),
+        };
+        let predicate = match Selection::predicate(selection) {
+            None => None,
+            Some(predicate) => Some(correlate(&predicate, &resolve, &outer_value)?),
         };
 
-        let result = executor.insert(ast::Insertion::new(&table_name, None, vec![]));
-        assert_eq!(result.is_err(), true);
+        Ok(ast::Selection::new_with_order_by(
+            Selection::table_name(selection),
+            Selection::columns(selection),
+            predicate,
+            Selection::order_by(selection),
+        ))
+    }
+
+    /// Values of the single column selected by a subquery
+    fn subquery(
+        &self,
+        selection: ast::Selection,
+        options: ExecOptions,
+        exceeded: &Rc<Cell<bool>>,
+    ) -> Result<Vec<Value>, String> {
+        let columns = Selection::columns(&selection);
+        let table_name = Selection::table_name(&selection).clone();
+        let rows = self.select_within(selection, options, exceeded.clone())?;
+        let num_columns = match columns {
+            ColumnSet::Count => 1,
+            ColumnSet::Names(column_names) => column_names.len(),
+            ColumnSet::Expressions(expressions) => expressions.len(),
+            ColumnSet::WildCard => self.tables[&table_name].columns().len(),
+        };
+        if num_columns != 1 {
+            return Err(format!(
+                "sub-select returns {} columns - expected 1",
+                num_columns
+            ));
+        }
+
+        Ok(rows.map(|mut row| row.remove(0)).collect())
+    }
+
+    /// Name and declared type of every column the selection returns, in
+    /// order, without running it. The count of `COUNT(*)` is an INTEGER.
+    pub fn describe<S: Selection>(&self, selection: &S) -> Result<Vec<(String, DataType)>, String> {
+        let table_name = selection.table_name();
+        let schema = match self.schema(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(schema) => schema,
+        };
+
+        match selection.columns() {
+            ColumnSet::WildCard => Ok(schema
+                .columns
+                .into_iter()
+                .map(|column| (column.name, column.data_type))
+                .collect()),
+            ColumnSet::Count => Ok(vec![("count(*)".to_string(), DataType::Integer)]),
+            ColumnSet::Names(column_names) => column_names
+                .into_iter()
+                .map(|column_name| {
+                    match schema
+                        .columns
+                        .iter()
+                        .find(|column| column.name == column_name)
+                    {
+                        None => Err(format!("no such column: {}", column_name)),
+                        Some(column) => Ok((column_name, column.data_type)),
+                    }
+                })
+                .collect(),
+            ColumnSet::Expressions(expressions) => expressions
+                .iter()
+                .map(|expression| Ok((expression.to_string(), data_type(expression, &schema)?)))
+                .collect(),
+        }
+    }
+
+    /// Selects rows along with the names of the selected columns, ready to
+    /// be printed
+    pub fn query<S: Selection>(&self, selection: S) -> Result<ResultSet, String> {
+        let columns = self
+            .describe(&selection)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let rows = self.select(selection)?.collect();
+        Ok(ResultSet::new(columns, rows))
+    }
+
+    /// Selects rows and decodes each of them into an `R`
+    pub fn query_as<R: FromRow, S: Selection>(&self, selection: S) -> Result<Vec<R>, String> {
+        let table_name = selection.table_name().clone();
+        let schema = match self.schema(&table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(schema) => schema,
+        };
+        let schema = match selection.columns() {
+            ColumnSet::WildCard => schema,
+            ColumnSet::Count => {
+                TableSchema::new(&schema.name, vec![ast::Column::new("count(*)", false)])
+            }
+            ColumnSet::Names(column_names) => TableSchema::new(
+                &schema.name,
+                column_names
+                    .iter()
+                    .filter_map(|column_name| {
+                        schema
+                            .columns
+                            .iter()
+                            .find(|column| column.name == *column_name)
+                            .cloned()
+                    })
+                    .collect(),
+            ),
+            ColumnSet::Expressions(_) => TableSchema::new(
+                &schema.name,
+                self.describe(&selection)?
+                    .into_iter()
+                    .map(|(name, data_type)| {
+                        ast::Column::new_with_data_type(&name, data_type, false, false)
+                    })
+                    .collect(),
+            ),
+        };
+
+        self.select(selection)?
+            .map(|row| R::from_row(&row, &schema))
+            .collect()
+    }
+
+    /// Parses a single statement and runs it. `new_table` creates the table
+    /// for a CREATE TABLE statement.
+    pub fn execute<F>(&mut self, sql: &str, new_table: F) -> Result<ExecOutcome, String>
+    where
+        F: FnOnce(TableSchema) -> Result<T, String>,
+    {
+        self.execute_with_options(sql, new_table, ExecOptions::default())
+    }
+
+    /// Runs a statement like `execute` but gives up on a SELECT that takes
+    /// more work than `options` allow
+    pub fn execute_with_options<F>(
+        &mut self,
+        sql: &str,
+        new_table: F,
+        options: ExecOptions,
+    ) -> Result<ExecOutcome, String>
+    where
+        F: FnOnce(TableSchema) -> Result<T, String>,
+    {
+        let ast = match parser::parse(sql) {
+            Ok(ast) => ast,
+            Err(err) => {
+                let is_batch = parse_batch(sql).map_or(false, |statements| statements.len() > 1);
+                if is_batch {
+                    return Err(
+                        "cannot execute more than one statement at a time, use execute_batch"
+                            .to_string(),
+                    );
+                }
+                return Err(err.to_string());
+            }
+        };
+
+        self.run(ast, new_table, options)
+    }
+
+    /// Parses every statement in `sql` and runs them in order, returning the
+    /// outcome of each. Nothing is run unless every statement parses. Running
+    /// stops at the first statement that fails, and the statements before it
+    /// keep their changes.
+    pub fn execute_batch<F>(
+        &mut self,
+        sql: &str,
+        mut new_table: F,
+    ) -> Result<Vec<ExecOutcome>, String>
+    where
+        F: FnMut(TableSchema) -> Result<T, String>,
+    {
+        let mut outcomes = vec![];
+        for ast in parse_batch(sql)? {
+            outcomes.push(self.run(ast, &mut new_table, ExecOptions::default())?);
+        }
+        Ok(outcomes)
+    }
+
+    fn run<F>(
+        &mut self,
+        ast: Ast,
+        new_table: F,
+        options: ExecOptions,
+    ) -> Result<ExecOutcome, String>
+    where
+        F: FnOnce(TableSchema) -> Result<T, String>,
+    {
+        match ast {
+            Ast::Exit => Err(".exit can only be used in the shell".to_string()),
+            Ast::Create(schema) => {
+                self.add_table(new_table(schema)?)?;
+                Ok(ExecOutcome::Done)
+            }
+            Ast::Insert(insertion) => {
+                let has_returning = Insertion::returning(&insertion).is_some();
+                let rows = self.insert(insertion)?;
+                if has_returning {
+                    Ok(ExecOutcome::Rows(rows))
+                } else {
+                    Ok(ExecOutcome::RowsAffected(1))
+                }
+            }
+            Ast::Select(selection) => Ok(ExecOutcome::Rows(
+                self.select_with_options(selection, options)?,
+            )),
+            Ast::Truncate(table_name) => {
+                let num_rows = self.row_count(&table_name)?;
+                self.truncate(&table_name)?;
+                Ok(ExecOutcome::RowsAffected(num_rows as usize))
+            }
+            Ast::Vacuum => self.vacuum().map(|_| ExecOutcome::Done),
+            Ast::Alter(alteration) => self.alter(alteration).map(|_| ExecOutcome::Done),
+            Ast::Explain(ast) => match *ast {
+                Ast::Select(selection) => {
+                    let plan = self.explain(selection)?;
+                    Ok(ExecOutcome::Rows(
+                        plan.lines()
+                            .map(|step| vec![Value::Text(step.to_string())])
+                            .collect(),
+                    ))
+                }
+                ast => Err(format!("cannot explain {:?}", ast)),
+            },
+            Ast::Savepoint(name) => self.savepoint(&name).map(|_| ExecOutcome::Done),
+            Ast::ReleaseSavepoint(name) => self.release_savepoint(&name).map(|_| ExecOutcome::Done),
+            Ast::RollbackTo(name) => self.rollback_to(&name).map(|_| ExecOutcome::Done),
+        }
+    }
+
+    /// Describes how the rows of a selection would be looked up, and whether
+    /// they would then be sorted, without looking them up
+    pub fn explain<S: Selection>(&self, selection: S) -> Result<String, String> {
+        let table_name = selection.table_name();
+        let table = match self.tables.get(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(table) => table,
+        };
+
+        let column_indices = Self::column_indices(table);
+        Self::projection(table, &selection.columns())?;
+        let predicate = selection
+            .predicate()
+            .map(|predicate| unqualify(&predicate, table_name));
+        if let Some(predicate) = &predicate {
+            for column in predicate.columns() {
+                if !column_indices.contains_key(column) {
+                    return Err(format!("no such column: {}", column));
+                }
+            }
+        }
+
+        let order_by = selection.order_by();
+        for term in &order_by {
+            if !column_indices.contains_key(&term.column) {
+                return Err(format!("no such column: {}", term.column));
+            }
+        }
+
+        let mut plan = match Self::access(table, predicate.as_ref()) {
+            Access::Scan => format!("SCAN {}", table_name),
+            Access::PrimaryKeys(_) | Access::PrimaryKeyRange(_, _) | Access::Key(_) => {
+                format!("SEARCH {} USING PRIMARY KEY", table_name)
+            }
+        };
+        if !order_by.is_empty() {
+            plan.push_str("\nUSE TEMP B-TREE FOR ORDER BY");
+        }
+        Ok(plan)
+    }
+
+    /// Uses the primary key index to narrow down the rows that need to be
+    /// filtered when the predicate is on the primary key, otherwise scans
+    /// the whole table.
+    fn scan(
+        table: &T,
+        predicate: &Predicate,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+        match Self::access(table, Some(predicate)) {
+            Access::Scan => table.select_rows(),
+            Access::PrimaryKeys(keys) => table.select_rows_by_primary_keys(&keys),
+            Access::PrimaryKeyRange(low, high) => {
+                table.select_rows_in_primary_key_range(&low, &high)
+            }
+            Access::Key(key) => Ok(Box::new(table.get(&key)?.into_iter())),
+        }
+    }
+
+    fn access(table: &T, predicate: Option<&Predicate>) -> Access {
+        let primary_key = match table.primary_key() {
+            None => return Self::composite_key_access(table, predicate),
+            Some(primary_key) => primary_key,
+        };
+
+        match predicate {
+            Some(Predicate::Comparison(column, Operator::Eq, value)) if *column == primary_key => {
+                Access::PrimaryKeys(vec![value.clone()])
+            }
+            Some(Predicate::In(column, values)) if *column == primary_key => {
+                Access::PrimaryKeys(values.clone())
+            }
+            Some(Predicate::Between(column, low, high)) if *column == primary_key => {
+                Access::PrimaryKeyRange(low.clone(), high.clone())
+            }
+            _ => Access::Scan,
+        }
+    }
+
+    /// Looks up a single row by its key when the predicate ANDs together an
+    /// equality on every column of a composite primary key, since the rows
+    /// are keyed by all of those columns together
+    fn composite_key_access(table: &T, predicate: Option<&Predicate>) -> Access {
+        let key_columns = table.schema().primary_key;
+        let predicate = match predicate {
+            Some(predicate) if key_columns.len() > 1 => predicate,
+            _ => return Access::Scan,
+        };
+
+        let mut equalities = HashMap::new();
+        collect_equalities(predicate, &mut equalities);
+        match key_columns
+            .iter()
+            .map(|column| {
+                equalities
+                    .get(column.as_str())
+                    .map(|value| (*value).clone())
+            })
+            .collect::<Option<Vec<Value>>>()
+        {
+            None => Access::Scan,
+            Some(key) => Access::Key(key),
+        }
+    }
+
+    /// Expressions that work out each selected column from a row, or None
+    /// if the whole row is selected
+    fn projection(table: &T, columns: &ColumnSet) -> Result<Option<Vec<Expression>>, String> {
+        let expressions = match columns {
+            ColumnSet::WildCard | ColumnSet::Count => return Ok(None),
+            ColumnSet::Names(column_names) => column_names
+                .iter()
+                .map(|column_name| Expression::Column(column_name.clone()))
+                .collect::<Vec<Expression>>(),
+            ColumnSet::Expressions(expressions) => expressions.clone(),
+        };
+        let column_indices = Self::column_indices(table);
+        for expression in &expressions {
+            for column_name in expression.columns() {
+                if !column_indices.contains_key(column_name) {
+                    return Err(format!("no such column: {}", column_name));
+                }
+            }
+        }
+        Ok(Some(expressions))
+    }
+
+    /// Collation of each column in row order
+    fn collations(table: &T) -> Vec<Collation> {
+        table
+            .schema()
+            .columns
+            .iter()
+            .map(|column| column.collation)
+            .collect()
+    }
+
+    fn column_indices(table: &T) -> HashMap<String, usize> {
+        table
+            .columns()
+            .iter()
+            .map(|column| (column.name().clone(), column.index()))
+            .collect()
+    }
+
+    /// Removes every row of the table. Rows of other tables that refer to
+    /// the removed rows are removed as well if their foreign key cascades,
+    /// otherwise nothing is removed at all.
+    pub fn truncate(&mut self, table_name: &str) -> Result<(), String> {
+        self.check_writable()?;
+        if !self.table_exists(table_name) {
+            return Err(format!("no such table: {}", table_name));
+        }
+        // every cascade is worked out before anything is removed, so that a
+        // foreign key that restricts the TRUNCATE leaves every table as it was
+        let mut cascaded = vec![];
+        self.cascade_delete(table_name, None, &mut cascaded)?;
+        let removed = match self.mutation_log {
+            None => vec![],
+            Some(_) => self.tables[table_name].select_rows()?.collect(),
+        };
+        for cascaded_delete in cascaded {
+            self.save_before_change(&cascaded_delete.table_name)?;
+            let child = self.tables.get_mut(&cascaded_delete.table_name).unwrap();
+            let child_schema = child.schema();
+            child.restore(child_schema, cascaded_delete.kept_rows)?;
+            self.log_mutations(
+                MutationKind::Delete,
+                &cascaded_delete.table_name,
+                cascaded_delete.removed_rows,
+            );
+        }
+        self.save_before_change(table_name)?;
+        self.tables.get_mut(table_name).unwrap().truncate();
+        self.log_mutations(MutationKind::Delete, table_name, removed);
+        Ok(())
+    }
+
+    /// Works out the ON DELETE action of every foreign key of another table
+    /// that refers to one of the `removed` keys of `table_name`, or to any
+    /// of its rows if every row is being removed. Nothing is changed: the
+    /// rows that cascading removes are added to `cascaded` in the order they
+    /// are removed in, and a foreign key that restricts the delete fails.
+    fn cascade_delete(
+        &self,
+        table_name: &str,
+        removed: Option<&[Vec<Value>]>,
+        cascaded: &mut Vec<CascadedDelete>,
+    ) -> Result<(), String> {
+        let children = self
+            .tables
+            .iter()
+            .filter(|(name, _)| *name != table_name)
+            .map(|(name, table)| (name.clone(), table.schema()))
+            .collect::<Vec<(String, TableSchema)>>();
+        for (child_name, child_schema) in children {
+            for foreign_key in &child_schema.foreign_keys {
+                if foreign_key.parent_table != table_name {
+                    continue;
+                }
+                let child = &self.tables[&child_name];
+                let column_indices = Self::column_indices(child);
+                let indices = foreign_key
+                    .columns
+                    .iter()
+                    .map(|column| column_indices[column])
+                    .collect::<Vec<usize>>();
+                // rows already removed by an earlier cascade are gone
+                let rows = match cascaded
+                    .iter()
+                    .rev()
+                    .find(|cascaded_delete| cascaded_delete.table_name == child_name)
+                {
+                    Some(cascaded_delete) => cascaded_delete.kept_rows.clone(),
+                    None => child.select_rows()?.collect(),
+                };
+                let (removed_rows, kept_rows): (Vec<Vec<Value>>, Vec<Vec<Value>>) =
+                    rows.into_iter().partition(|row| {
+                        let key = project(row, &indices);
+                        !key.contains(&Value::Null)
+                            && removed.map_or(true, |removed| removed.contains(&key))
+                    });
+                if removed_rows.is_empty() {
+                    continue;
+                }
+
+                match foreign_key.on_delete {
+                    ForeignKeyAction::Restrict => {
+                        return Err("FOREIGN KEY constraint failed".to_string());
+                    }
+                    ForeignKeyAction::Cascade => {
+                        let key_indices = child_schema
+                            .key_columns()
+                            .iter()
+                            .map(|column| column_indices[column])
+                            .collect::<Vec<usize>>();
+                        let removed_keys = removed_rows
+                            .iter()
+                            .map(|row| project(row, &key_indices))
+                            .collect::<Vec<Vec<Value>>>();
+                        cascaded.push(CascadedDelete {
+                            table_name: child_name.clone(),
+                            kept_rows,
+                            removed_rows,
+                        });
+                        self.cascade_delete(&child_name, Some(&removed_keys), cascaded)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a savepoint that changes can later be rolled back to.
+    ///
+    /// Savepoints nest and a name may be reused, in which case the most
+    /// recent savepoint with that name is the one released or rolled back to.
+    ///
+    /// Nothing is copied when the savepoint starts. Instead, the rows of a
+    /// table are copied just before it is first changed, so a savepoint costs
+    /// as much as the tables changed while it is open.
+    pub fn savepoint(&mut self, name: &str) -> Result<(), String> {
+        self.savepoints.push(Savepoint {
+            name: name.to_string(),
+            table_names: self.tables.keys().cloned().collect(),
+            tables: HashMap::new(),
+            mutation_log_len: self.mutation_log.as_ref().map_or(0, Vec::len),
+        });
+        Ok(())
+    }
+
+    /// Copies the table named `table_name` into every open savepoint that
+    /// started before it was last changed, so that those savepoints can put
+    /// it back the way it is now
+    fn save_before_change(&mut self, table_name: &str) -> Result<(), String> {
+        let table = match self.tables.get(table_name) {
+            None => return Ok(()),
+            Some(table) => table,
+        };
+
+        let mut saved = None;
+        for savepoint in &mut self.savepoints {
+            if !savepoint.table_names.contains(table_name)
+                || savepoint.tables.contains_key(table_name)
+            {
+                continue;
+            }
+            if saved.is_none() {
+                saved = Some(Rc::new(SavedTable {
+                    schema: table.schema(),
+                    rows: table.select_rows()?.collect(),
+                }));
+            }
+            savepoint
+                .tables
+                .insert(table_name.to_string(), saved.clone().unwrap());
+        }
+        Ok(())
+    }
+
+    /// Keeps the changes made since the savepoint named `name` as part of
+    /// the enclosing savepoint, if any, and closes it along with every
+    /// savepoint started after it
+    pub fn release_savepoint(&mut self, name: &str) -> Result<(), String> {
+        let index = self.find_savepoint(name)?;
+        self.savepoints.truncate(index);
+        Ok(())
+    }
+
+    /// Undoes every change made since the savepoint named `name`, closing
+    /// the savepoints started after it. The savepoint itself stays open.
+    /// Only the tables changed since the savepoint are rebuilt.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), String> {
+        let index = self.find_savepoint(name)?;
+        self.savepoints.truncate(index + 1);
+
+        let savepoint = &self.savepoints[index];
+        if let Some(mutation_log) = &mut self.mutation_log {
+            mutation_log.truncate(savepoint.mutation_log_len);
+        }
+        self.tables
+            .retain(|table_name, _| savepoint.table_names.contains(table_name));
+        for (table_name, saved) in &savepoint.tables {
+            self.tables
+                .get_mut(table_name)
+                .unwrap()
+                .restore(saved.schema.clone(), saved.rows.clone())?;
+        }
+        Ok(())
+    }
+
+    fn find_savepoint(&self, name: &str) -> Result<usize, String> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no such savepoint: {}", name))
+    }
+
+    pub fn alter(&mut self, alteration: Alteration) -> Result<(), String> {
+        self.check_writable()?;
+        alteration.validate()?;
+        if let Value::Parameter(index) = alteration.default {
+            return Err(format!("unbound parameter ?{}", index));
+        }
+        self.save_before_change(&alteration.table_name)?;
+        match self.tables.get_mut(&alteration.table_name) {
+            None => Err(format!("no such table: {}", alteration.table_name)),
+            Some(table) => table.add_column(alteration.column, alteration.default),
+        }
+    }
+
+    /// Changes that would turn the live table named like `desired` into it
+    pub fn diff_schema(&self, desired: &TableSchema) -> Result<Vec<SchemaChange>, String> {
+        match self.tables.get(&desired.name) {
+            None => Err(format!("no such table: {}", desired.name)),
+            Some(table) => Ok(table.schema().diff(desired)),
+        }
+    }
+
+    /// Adds the columns that `desired` has and the live table lacks, with
+    /// existing rows backfilled with NULL. Dropping a column or changing its
+    /// type isn't supported, so if the table needs either nothing is changed.
+    pub fn migrate(&mut self, desired: &TableSchema) -> Result<(), String> {
+        self.check_writable()?;
+        let mut alterations = vec![];
+        for change in self.diff_schema(desired)? {
+            match change {
+                SchemaChange::AddColumn(column) => {
+                    let alteration = Alteration::new(&desired.name, column, None);
+                    alteration.validate()?;
+                    alterations.push(alteration);
+                }
+                SchemaChange::DropColumn(column) => {
+                    return Err(format!(
+                        "cannot drop column {} of table {}",
+                        column, desired.name
+                    ))
+                }
+                SchemaChange::ChangeType { column, from, to } => {
+                    return Err(format!(
+                        "cannot change the type of column {} of table {} from {:?} to {:?}",
+                        column, desired.name, from, to
+                    ))
+                }
+            }
+        }
+
+        for alteration in alterations {
+            self.alter(alteration)?;
+        }
+        Ok(())
+    }
+
+    /// Every row of the table as a JSON array of objects keyed by column name
+    pub fn export_table(&self, table_name: &str) -> Result<String, String> {
+        let table = match self.tables.get(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(table) => table,
+        };
+
+        let schema = table.schema();
+        let objects = table
+            .select_rows()?
+            .map(|row| {
+                schema
+                    .columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| value.to_json().map(|json| (column.name.clone(), json)))
+                    .collect::<Result<serde_json::Map<String, serde_json::Value>, String>>()
+            })
+            .collect::<Result<Vec<serde_json::Map<String, serde_json::Value>>, String>>()?;
+
+        serde_json::to_string(&objects).map_err(|err| err.to_string())
+    }
+
+    /// Inserts the rows of a JSON array of objects keyed by column name.
+    ///
+    /// Every object is checked before the first row is inserted so that
+    /// either all of the rows are imported or none of them are.
+    pub fn import_table(&mut self, table_name: &str, json: &str) -> Result<usize, String> {
+        self.check_writable()?;
+        let table = match self.tables.get(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(table) => table,
+        };
+
+        let objects: Vec<serde_json::Map<String, serde_json::Value>> =
+            serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let column_names = table
+            .columns()
+            .iter()
+            .map(|column| column.name().clone())
+            .collect::<HashSet<String>>();
+
+        let mut rows = vec![];
+        for object in objects {
+            let mut row = HashMap::new();
+            for (column_name, value) in object {
+                if !column_names.contains(&column_name) {
+                    return Err(format!("no such column: {}", column_name));
+                }
+                let value = match Value::from_json(&value) {
+                    Some(value) => value,
+                    None => {
+                        return Err(format!(
+                            "expected an integer, text, boolean or blob for column {} but got {}",
+                            column_name, value
+                        ))
+                    }
+                };
+                row.insert(column_name, value);
+            }
+            rows.push(row);
+        }
+
+        let schema = table.schema();
+        for row in &rows {
+            check_bound(row)?;
+            self.run_before_insert_hooks(&schema, row)?;
+            check_constraints(&schema, row)?;
+            self.check_foreign_keys(&schema, row)?;
+        }
+        self.insert_rows(table_name, rows)
+    }
+
+    /// Inserts CSV records into the table and returns how many there were.
+    ///
+    /// Fields are matched to columns by the header when there is one and by
+    /// position otherwise, and read as the type of their column. Empty fields
+    /// are NULL. Like `import_table`, either every record is inserted or none
+    /// are.
+    pub fn import_csv<R: Read>(
+        &mut self,
+        table_name: &str,
+        mut reader: R,
+        has_header: bool,
+    ) -> Result<usize, String> {
+        self.check_writable()?;
+        let table = match self.tables.get(table_name) {
+            None => return Err(format!("no such table: {}", table_name)),
+            Some(table) => table,
+        };
+
+        let mut csv = String::new();
+        reader
+            .read_to_string(&mut csv)
+            .map_err(|err| err.to_string())?;
+        let mut lines = csv
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty())
+            .collect::<Vec<(usize, &str)>>();
+
+        let table_columns = table.schema().columns;
+        let columns = if has_header && !lines.is_empty() {
+            let (_, header) = lines.remove(0);
+            let mut columns = vec![];
+            for column_name in split_csv_record(header) {
+                let column_name = column_name.trim();
+                match table_columns
+                    .iter()
+                    .find(|column| column.name == column_name)
+                {
+                    None => return Err(format!("no such column: {}", column_name)),
+                    Some(column) => columns.push(column.clone()),
+                }
+            }
+            columns
+        } else {
+            table_columns
+        };
+
+        let mut rows = vec![];
+        for (line_number, line) in lines {
+            let fields = split_csv_record(line);
+            if fields.len() != columns.len() {
+                return Err(format!(
+                    "line {}: expected {} values but got {}",
+                    line_number,
+                    columns.len(),
+                    fields.len()
+                ));
+            }
+
+            let mut row = HashMap::new();
+            for (column, field) in columns.iter().zip(fields) {
+                let value = parse_csv_field(&field, column.data_type).map_err(|expected| {
+                    format!(
+                        "line {}, column {}: expected {} but got {:?}",
+                        line_number,
+                        column.name,
+                        expected,
+                        field.trim()
+                    )
+                })?;
+                row.insert(column.name.clone(), value);
+            }
+            rows.push(row);
+        }
+
+        let schema = table.schema();
+        for row in &rows {
+            check_bound(row)?;
+            self.run_before_insert_hooks(&schema, row)?;
+            check_constraints(&schema, row)?;
+            self.check_foreign_keys(&schema, row)?;
+        }
+        self.insert_rows(table_name, rows)
+    }
+
+    /// Inserts imported rows all at once, so that the table checks their
+    /// keys against each other and the stored rows before storing any
+    fn insert_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<HashMap<String, Value>>,
+    ) -> Result<usize, String> {
+        self.save_before_change(table_name)?;
+        let table = self.tables.get_mut(table_name).unwrap();
+        let schema = table.schema();
+        let primary_key = table.primary_key();
+        let mut rows = rows
+            .iter()
+            .map(|row| row_values(&schema, row))
+            .collect::<Vec<Vec<Value>>>();
+
+        // rows without a rowid are inserted last so that the rowids assigned
+        // to them can't collide with a rowid that is yet to be inserted
+        if let Some(index) = schema
+            .columns
+            .iter()
+            .position(|column| Some(&column.name) == primary_key.as_ref())
+        {
+            rows.sort_by_key(|row| row[index] == Value::Null);
+        }
+        let inserted = table.insert_rows(rows)?;
+        let num_rows = inserted.len();
+        self.log_mutations(MutationKind::Insert, table_name, inserted);
+
+        Ok(num_rows)
+    }
+
+    pub fn row_count(&self, table_name: &str) -> Result<u64, String> {
+        match self.tables.get(table_name) {
+            None => Err(format!("no such table: {}", table_name)),
+            Some(table) => Ok(table.row_count() as u64),
+        }
+    }
+
+    pub fn vacuum(&mut self) -> Result<(), String> {
+        self.check_writable()?;
+        for table in self.tables.values_mut() {
+            table.vacuum();
+        }
+        Ok(())
+    }
+
+    /// Names of every table, sorted alphabetically
+    pub fn table_names(&self) -> Vec<String> {
+        let mut table_names = self.tables.keys().cloned().collect::<Vec<String>>();
+        table_names.sort();
+
+        table_names
+    }
+
+    pub fn schema(&self, table_name: &str) -> Option<TableSchema> {
+        self.tables.get(table_name).map(|table| table.schema())
+    }
+
+    fn table_exists(&self, table_name: &str) -> bool {
+        return self.tables.get(table_name).is_some();
+    }
+}
+
+/// Ends `rows` early, setting `exceeded`, once reading the next row would
+/// take more work than `options` allow
+fn limit(
+    rows: Box<dyn Iterator<Item = Vec<Value>>>,
+    options: ExecOptions,
+    exceeded: Rc<Cell<bool>>,
+) -> Box<dyn Iterator<Item = Vec<Value>>> {
+    if options == ExecOptions::default() {
+        return rows;
+    }
+
+    let mut num_scanned = 0;
+    Box::new(rows.take_while(move |_| {
+        num_scanned += 1;
+        let is_exceeded = options
+            .max_scanned_rows
+            .map_or(false, |max_scanned_rows| num_scanned > max_scanned_rows)
+            || options
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline);
+        if is_exceeded {
+            exceeded.set(true);
+        }
+        !is_exceeded
+    }))
+}
+
+/// Value of `expression` for `row`
+fn evaluate_expression(
+    expression: &Expression,
+    row: &[Value],
+    column_indices: &HashMap<String, usize>,
+) -> Value {
+    match expression {
+        Expression::Column(column) => row[column_indices[column]].clone(),
+        Expression::Value(value) => value.clone(),
+        Expression::Cast { expr, to } => evaluate_expression(expr, row, column_indices).cast(*to),
+    }
+}
+
+fn evaluate_expressions(
+    expressions: &[Expression],
+    row: &[Value],
+    column_indices: &HashMap<String, usize>,
+) -> Vec<Value> {
+    expressions
+        .iter()
+        .map(|expression| evaluate_expression(expression, row, column_indices))
+        .collect()
+}
+
+/// Type of the values of `expression` on a table with `schema`. A CAST
+/// decides the type of its result whatever it is given.
+fn data_type(expression: &Expression, schema: &TableSchema) -> Result<DataType, String> {
+    match expression {
+        Expression::Column(column_name) => schema
+            .columns
+            .iter()
+            .find(|column| column.name == *column_name)
+            .map(|column| column.data_type)
+            .ok_or_else(|| format!("no such column: {}", column_name)),
+        Expression::Value(value) => Ok(match value {
+            Value::Integer(_) => DataType::Integer,
+            Value::Text(_) => DataType::Text,
+            Value::Boolean(_) => DataType::Boolean,
+            Value::Blob(_) => DataType::Blob,
+            Value::Null | Value::Parameter(_) => DataType::Any,
+        }),
+        Expression::Cast { expr, to } => {
+            data_type(expr, schema)?;
+            Ok(*to)
+        }
+    }
+}
+
+fn project(row: &[Value], indices: &[usize]) -> Vec<Value> {
+    indices.iter().map(|index| row[*index].clone()).collect()
+}
+
+/// Splits a CSV record on commas outside of double quotes, where `""`
+/// inside quotes stands for a literal quote
+fn split_csv_record(record: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::replace(&mut field, String::new())),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+/// Value of a CSV field in a column of type `data_type`, written the way a
+/// value is displayed. An empty field is NULL, and a column without a type
+/// holds an integer if the field is one and text otherwise. Fails with what
+/// was expected instead.
+fn parse_csv_field(field: &str, data_type: DataType) -> Result<Value, &'static str> {
+    let trimmed = field.trim();
+    if trimmed.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    match data_type {
+        DataType::Integer => trimmed
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| "an integer"),
+        DataType::Text => Ok(Value::Text(field.to_string())),
+        DataType::Boolean => match trimmed.to_ascii_lowercase().as_str() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err("a boolean"),
+        },
+        DataType::Blob => {
+            let hex = match trimmed.get(..2) {
+                Some("X'") | Some("x'") if trimmed.len() > 2 && trimmed.ends_with('\'') => {
+                    &trimmed[2..trimmed.len() - 1]
+                }
+                _ => return Err("a blob"),
+            };
+            Value::blob_from_hex(hex).map_err(|_| "a blob")
+        }
+        DataType::Any => Ok(trimmed
+            .parse::<i64>()
+            .map_or_else(|_| Value::Text(field.to_string()), Value::Integer)),
+    }
+}
+
+/// Statements of `sql` in order, each ending at the first `;` that completes
+/// it
+fn parse_batch(sql: &str) -> Result<Vec<Ast>, String> {
+    let mut statements = vec![];
+    let mut rest = sql;
+    while !rest.trim().is_empty() {
+        let statement = rest
+            .match_indices(';')
+            .map(|(index, _)| index + 1)
+            .find_map(|end| parser::parse(&rest[..end]).ok().map(|ast| (ast, end)));
+        match statement {
+            Some((ast, end)) => {
+                statements.push(ast);
+                rest = &rest[end..];
+            }
+            None => {
+                statements.push(parser::parse(rest).map_err(|err| err.to_string())?);
+                break;
+            }
+        }
+    }
+
+    Ok(statements)
+}
+
+/// Column referred to by the predicate of a subquery
+enum ColumnRef {
+    /// Column of the subquery's own table
+    Inner(String),
+    /// Column of the table of the query around the subquery
+    Outer(String),
+}
+
+/// Predicate of a subquery with every column of the query around it
+/// replaced by its value. Such columns can only be compared with a column of
+/// the subquery.
+fn correlate(
+    predicate: &Predicate,
+    resolve: &dyn Fn(&str) -> ColumnRef,
+    outer_value: &dyn Fn(&str) -> Result<Value, String>,
+) -> Result<Predicate, String> {
+    let correlate = |predicate| correlate(predicate, resolve, outer_value);
+    Ok(match predicate {
+        Predicate::ColumnComparison(left, operator, right) => match (resolve(left), resolve(right))
+        {
+            (ColumnRef::Inner(left), ColumnRef::Inner(right)) => {
+                Predicate::ColumnComparison(left, operator.clone(), right)
+            }
+            (ColumnRef::Inner(left), ColumnRef::Outer(right)) => {
+                Predicate::Comparison(left, operator.clone(), outer_value(&right)?)
+            }
+            (ColumnRef::Outer(left), ColumnRef::Inner(right)) => {
+                Predicate::Comparison(right, flip(operator), outer_value(&left)?)
+            }
+            (ColumnRef::Outer(_), ColumnRef::Outer(_)) => {
+                return Err(format!(
+                    "{} and {} are both columns of the outer query",
+                    left, right
+                ))
+            }
+        },
+        Predicate::And(left, right) => {
+            Predicate::And(Box::new(correlate(left)?), Box::new(correlate(right)?))
+        }
+        Predicate::Or(left, right) => {
+            Predicate::Or(Box::new(correlate(left)?), Box::new(correlate(right)?))
+        }
+        Predicate::Not(predicate) => Predicate::Not(Box::new(correlate(predicate)?)),
+        predicate => {
+            for column in predicate.columns() {
+                if let ColumnRef::Outer(_) = resolve(column) {
+                    return Err(format!("no such column: {}", column));
+                }
+            }
+            predicate.rename_columns(&|column| match resolve(column) {
+                ColumnRef::Inner(column) | ColumnRef::Outer(column) => column,
+            })
+        }
+    })
+}
+
+/// Values that the ANDed equalities of `predicate` require columns to equal
+fn collect_equalities<'a>(predicate: &'a Predicate, equalities: &mut HashMap<&'a str, &'a Value>) {
+    match predicate {
+        Predicate::Comparison(column, Operator::Eq, value) => {
+            equalities.insert(column, value);
+        }
+        Predicate::And(left, right) => {
+            collect_equalities(left, equalities);
+            collect_equalities(right, equalities);
+        }
+        _ => {}
+    }
+}
+
+/// Operator that gives the same result with its operands swapped
+fn flip(operator: &Operator) -> Operator {
+    match operator {
+        Operator::Lt => Operator::Gt,
+        Operator::LtEq => Operator::GtEq,
+        Operator::Gt => Operator::Lt,
+        Operator::GtEq => Operator::LtEq,
+        operator => operator.clone(),
+    }
+}
+
+/// Predicate with the columns that are qualified with `table_name` stripped
+/// of it
+fn unqualify(predicate: &Predicate, table_name: &str) -> Predicate {
+    predicate.rename_columns(&|column| match column.find('.') {
+        Some(dot) if column[..dot].eq_ignore_ascii_case(table_name) => {
+            column[dot + 1..].to_string()
+        }
+        _ => column.to_string(),
+    })
+}
+
+/// Values of a row to be inserted in the column order of `schema`, where a
+/// column without a value is NULL
+fn row_values(schema: &TableSchema, row: &HashMap<String, Value>) -> Vec<Value> {
+    schema
+        .columns
+        .iter()
+        .map(|column| row.get(&column.name).cloned().unwrap_or(Value::Null))
+        .collect()
+}
+
+/// Fails if the row holds a parameter of a prepared statement that was never
+/// bound, naming the lowest numbered one, since only values can be stored
+fn check_bound(row: &HashMap<String, Value>) -> Result<(), String> {
+    let unbound = row
+        .values()
+        .filter_map(|value| match value {
+            Value::Parameter(index) => Some(*index),
+            _ => None,
+        })
+        .min();
+    match unbound {
+        None => Ok(()),
+        Some(index) => Err(format!("unbound parameter ?{}", index)),
+    }
+}
+
+/// Checks that none of the CHECK constraints of the table with `schema`
+/// evaluate to false for a row to be inserted. A constraint that evaluates
+/// to NULL passes.
+fn check_constraints(schema: &TableSchema, row: &HashMap<String, Value>) -> Result<(), String> {
+    let column_indices = schema
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| (column.name.clone(), index))
+        .collect::<HashMap<String, usize>>();
+    let collations = schema
+        .columns
+        .iter()
+        .map(|column| column.collation)
+        .collect::<Vec<Collation>>();
+    let values = row_values(schema, row);
+    for check in &schema.checks {
+        if evaluate(check, &values, &column_indices, &collations) == Some(false) {
+            return Err(format!("CHECK constraint failed: {}", schema.name));
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates `predicate` against `row` using SQL's three-valued logic, where
+/// `None` means the result is unknown because a NULL was involved. Text is
+/// compared with the collation of the column, or of the column on the left
+/// when two columns are compared.
+fn evaluate(
+    predicate: &Predicate,
+    row: &[Value],
+    column_indices: &HashMap<String, usize>,
+    collations: &[Collation],
+) -> Option<bool> {
+    match predicate {
+        Predicate::ColumnComparison(left, operator, right) => compare(
+            &row[column_indices[left]],
+            operator,
+            &row[column_indices[right]],
+            collations[column_indices[left]],
+        ),
+        Predicate::Constant(value) => Some(*value),
+        Predicate::Comparison(column, operator, operand) => compare(
+            &row[column_indices[column]],
+            operator,
+            operand,
+            collations[column_indices[column]],
+        ),
+        Predicate::ExpressionComparison(expression, operator, operand) => {
+            // a CAST keeps the collation of the column it converts
+            let collation = match expression.columns().first() {
+                Some(column) => collations[column_indices[*column]],
+                None => Collation::default(),
+            };
+            compare(
+                &evaluate_expression(expression, row, column_indices),
+                operator,
+                operand,
+                collation,
+            )
+        }
+        Predicate::In(column, values) => {
+            let value = &row[column_indices[column]];
+            let collation = collations[column_indices[column]];
+            if values.is_empty() {
+                Some(false)
+            } else if *value == Value::Null {
+                None
+            } else if values
+                .iter()
+                .any(|other| collation.key(other) == collation.key(value))
+            {
+                Some(true)
+            } else if values.contains(&Value::Null) {
+                None
+            } else {
+                Some(false)
+            }
+        }
+        Predicate::Between(column, low, high) => {
+            let value = &row[column_indices[column]];
+            let collation = collations[column_indices[column]];
+            and(
+                compare(value, &Operator::GtEq, low, collation),
+                compare(value, &Operator::LtEq, high, collation),
+            )
+        }
+        Predicate::IsNull(column) => Some(row[column_indices[column]] == Value::Null),
+        Predicate::IsNotNull(column) => Some(row[column_indices[column]] != Value::Null),
+        Predicate::Like(column, pattern, escape) => {
+            let pattern = pattern.chars().collect::<Vec<char>>();
+            match &row[column_indices[column]] {
+                Value::Null => None,
+                Value::Text(s) => Some(like(&s.chars().collect::<Vec<char>>(), &pattern, *escape)),
+                value => Some(like(
+                    &value.to_string().chars().collect::<Vec<char>>(),
+                    &pattern,
+                    *escape,
+                )),
+            }
+        }
+        Predicate::And(left, right) => and(
+            evaluate(left, row, column_indices, collations),
+            evaluate(right, row, column_indices, collations),
+        ),
+        Predicate::Or(left, right) => or(
+            evaluate(left, row, column_indices, collations),
+            evaluate(right, row, column_indices, collations),
+        ),
+        Predicate::Not(predicate) => {
+            evaluate(predicate, row, column_indices, collations).map(|b| !b)
+        }
+        Predicate::InSubquery(_, _)
+        | Predicate::ComparisonSubquery(_, _, _)
+        | Predicate::Exists(_) => {
+            unreachable!("subqueries are run before the predicate is evaluated")
+        }
+    }
+}
+
+/// Part of a LIKE pattern
+#[derive(Clone, Copy, PartialEq)]
+enum LikeToken {
+    /// `%`, which matches any run of characters
+    AnyRun,
+    /// `_`, which matches any one character
+    AnyChar,
+    Char(char),
+}
+
+/// Matches `text` against a LIKE `pattern`, ignoring the case of ASCII letters.
+///
+/// Only the most recent `%` is ever retried, by letting it match one more
+/// character, so matching takes at most `text.len() * pattern.len()` steps
+/// however many `%` there are.
+fn like(text: &[char], pattern: &[char], escape: Option<char>) -> bool {
+    let mut tokens = vec![];
+    let mut pattern = pattern.iter();
+    while let Some(c) = pattern.next() {
+        tokens.push(match c {
+            c if Some(*c) == escape => match pattern.next() {
+                Some(escaped) => LikeToken::Char(*escaped),
+                None => return false,
+            },
+            '%' => LikeToken::AnyRun,
+            '_' => LikeToken::AnyChar,
+            c => LikeToken::Char(*c),
+        });
+    }
+
+    let (mut t, mut p) = (0, 0);
+    // position in the pattern after the last `%` and in the text where what
+    // it matches ends
+    let mut retry = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(LikeToken::AnyRun) => {
+                p += 1;
+                retry = Some((p, t));
+                continue;
+            }
+            Some(LikeToken::AnyChar) => {
+                t += 1;
+                p += 1;
+                continue;
+            }
+            Some(LikeToken::Char(c)) if c.eq_ignore_ascii_case(&text[t]) => {
+                t += 1;
+                p += 1;
+                continue;
+            }
+            _ => (),
+        }
+        match retry {
+            None => return false,
+            Some((after_any_run, end)) => {
+                p = after_any_run;
+                t = end + 1;
+                retry = Some((after_any_run, t));
+            }
+        }
+    }
+
+    tokens[p..].iter().all(|token| *token == LikeToken::AnyRun)
+}
+
+/// Orders two rows by the values at the indices of `keys`, falling back on
+/// the next key whenever they are equal
+fn compare_rows(
+    a: &[Value],
+    b: &[Value],
+    keys: &[(usize, SortOrder, NullsOrder, Collation)],
+) -> Ordering {
+    for (index, order, nulls, collation) in keys {
+        let ordering = match (&a[*index], &b[*index]) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => match nulls {
+                NullsOrder::First => Ordering::Less,
+                NullsOrder::Last => Ordering::Greater,
+            },
+            (_, Value::Null) => match nulls {
+                NullsOrder::First => Ordering::Greater,
+                NullsOrder::Last => Ordering::Less,
+            },
+            (a, b) => {
+                let (a, b) = (collation.key(a), collation.key(b));
+                match order {
+                    SortOrder::Asc => a.cmp(&b),
+                    SortOrder::Desc => b.cmp(&a),
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn compare(
+    value: &Value,
+    operator: &Operator,
+    operand: &Value,
+    collation: Collation,
+) -> Option<bool> {
+    if *value == Value::Null || *operand == Value::Null {
+        return None;
+    }
+    let (value, operand) = (&collation.key(value), &collation.key(operand));
+
+    Some(match operator {
+        Operator::Eq => value == operand,
+        Operator::NotEq => value != operand,
+        Operator::Lt => value < operand,
+        Operator::LtEq => value <= operand,
+        Operator::Gt => value > operand,
+        Operator::GtEq => value >= operand,
+    })
+}
+
+fn and(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bptree, row, table};
+    use mockers::Scenario;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    type RowTree = bptree::BPTree<table::Key, Vec<Value>>;
+
+    fn new_executor_with_table(schema: TableSchema) -> Executor<table::Table<RowTree>> {
+        let mut executor = Executor::new();
+        let rows = RowTree::new(4, 16, bptree::Serializer::Mock);
+        executor
+            .add_table(table::Table::new(schema, rows).unwrap())
+            .unwrap();
+
+        executor
+    }
+
+    fn apples_schema() -> TableSchema {
+        TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("slices", false),
+            ],
+        )
+    }
+
+    #[test]
+    fn should_fail_to_create_a_table_if_one_with_same_name_already_exists() {
+        let scenario = Scenario::new();
+        let table_name = "apples".to_string();
+        let (table, _) = scenario.create_mock_for::<dyn Table>();
+        let (table2, table2_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table2_handle.name().and_return("apples".to_string()));
+        let mut tables: HashMap<String, TableMock> = HashMap::new();
+        tables.insert(table_name, table);
+
+        let mut executor = Executor {
+            tables,
+            savepoints: vec![],
+            read_only: false,
+            before_insert_hooks: HashMap::new(),
+            comparison_mode: ComparisonMode::default(),
+            mutation_log: None,
+        };
+        let result = executor.add_table(table2);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn should_fail_to_insert_row_if_table_does_not_exist() {
+        let table_name = "oranges".to_string();
+        let mut executor = Executor::<TableMock> {
+            tables: HashMap::new(),
+            savepoints: vec![],
+            read_only: false,
+            before_insert_hooks: HashMap::new(),
+            comparison_mode: ComparisonMode::default(),
+            mutation_log: None,
+        };
+
+        let result = executor.insert(ast::Insertion::new(&table_name, None, vec![]));
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn table_names_are_sorted() {
+        let scenario = Scenario::new();
+        let mut executor = Executor::<TableMock>::new();
+        for table_name in &["pears", "apples", "oranges"] {
+            let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+            scenario.expect(table_handle.name().and_return(table_name.to_string()));
+            scenario.expect(table_handle.schema().and_return(TableSchema::new(
+                table_name,
+                vec![ast::Column::new("id", true)],
+            )));
+            executor.add_table(table).unwrap();
+        }
+
+        assert_eq!(
+            executor.table_names(),
+            vec![
+                "apples".to_string(),
+                "oranges".to_string(),
+                "pears".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn schema_of_a_table_is_returned() {
+        let scenario = Scenario::new();
+        let schema = TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("slices", false),
+            ],
+        );
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table_handle.name().and_return("apples".to_string()));
+        scenario.expect(
+            table_handle
+                .schema()
+                .and_return_clone(schema.clone())
+                .times(2),
+        );
+        let mut executor = Executor::<TableMock>::new();
+        executor.add_table(table).unwrap();
+
+        assert_eq!(executor.schema("apples"), Some(schema));
+        assert_eq!(executor.schema("oranges"), None);
+    }
+
+    #[test]
+    fn insert_many_looks_up_the_table_once_for_every_row() {
+        let scenario = Scenario::new();
+        let schema = apples_schema();
+        let rows = (1..=1000)
+            .map(|id| vec![Value::Integer(id), Value::Integer(id * 4)])
+            .collect::<Vec<Vec<Value>>>();
+        let (table, table_handle) = scenario.create_mock_for::<dyn Table>();
+        scenario.expect(table_handle.name().and_return("apples".to_string()));
+        // once when the table is added and once for the whole batch
+        scenario.expect(
+            table_handle
+                .schema()
+                .and_return_clone(schema.clone())
+                .times(2),
+        );
+        scenario.expect(
+            table_handle
+                .insert_rows(mockers::matchers::eq(rows.clone()))
+                .and_return(Ok(rows.clone())),
+        );
+        let mut executor = Executor::<TableMock>::new();
+        executor.add_table(table).unwrap();
+
+        assert_eq!(executor.insert_many("apples", rows.into_iter()), Ok(1000));
+    }
+
+    #[test]
+    fn should_fail_to_insert_row_with_unknown_column_name() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let result = executor.insert(ast::Insertion::new(
+            "apples",
+            Some(vec!["seeds".to_string()]),
+            vec![Value::Integer(3)],
+        ));
+        assert_eq!(result, Err("no such column: seeds".to_string()));
+    }
+
+    #[test]
+    fn should_fail_to_insert_row_with_duplicate_column_name() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let result = executor.insert(ast::Insertion::new(
+            "apples",
+            Some(vec!["slices".to_string(), "slices".to_string()]),
+            vec![Value::Integer(3), Value::Integer(4)],
+        ));
+        assert_eq!(result, Err("duplicate column name: slices".to_string()));
+    }
+
+    #[test]
+    fn named_columns_are_inserted_by_name_rather_than_order() {
+        let mut executor = new_executor_with_table(apples_schema());
+        executor
+            .insert(ast::Insertion::new(
+                "apples",
+                Some(vec!["slices".to_string(), "id".to_string()]),
+                vec![Value::Integer(8), Value::Integer(1)],
+            ))
+            .unwrap();
+
+        let rows = executor
+            .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(8)]]);
+    }
+
+    fn insert_apples(executor: &mut Executor<table::Table<RowTree>>, rows: Vec<(i64, Value)>) {
+        for (id, slices) in rows {
+            executor
+                .insert(ast::Insertion::new(
+                    "apples",
+                    None,
+                    vec![Value::Integer(id), slices],
+                ))
+                .unwrap();
+        }
+    }
+
+    fn select_where(
+        executor: &Executor<table::Table<RowTree>>,
+        predicate: Predicate,
+    ) -> Vec<Vec<Value>> {
+        executor
+            .select(ast::Selection::new(
+                "apples",
+                ColumnSet::Names(vec!["id".to_string()]),
+                Some(predicate),
+            ))
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn in_predicate_selects_matching_rows() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            (1..=5).map(|id| (id, Value::Integer(id * 10))).collect(),
+        );
+
+        let by_primary_key = select_where(
+            &executor,
+            Predicate::In(
+                "id".to_string(),
+                vec![Value::Integer(4), Value::Integer(2), Value::Integer(9)],
+            ),
+        );
+        assert_eq!(
+            by_primary_key,
+            vec![vec![Value::Integer(2)], vec![Value::Integer(4)]]
+        );
+
+        let by_column = select_where(
+            &executor,
+            Predicate::In(
+                "slices".to_string(),
+                vec![Value::Integer(30), Value::Integer(50)],
+            ),
+        );
+        assert_eq!(
+            by_column,
+            vec![vec![Value::Integer(3)], vec![Value::Integer(5)]]
+        );
+    }
+
+    #[test]
+    fn empty_in_predicate_selects_nothing() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(10))]);
+
+        let by_primary_key = select_where(&executor, Predicate::In("id".to_string(), vec![]));
+        assert_eq!(by_primary_key, Vec::<Vec<Value>>::new());
+
+        let by_column = select_where(&executor, Predicate::In("slices".to_string(), vec![]));
+        assert_eq!(by_column, Vec::<Vec<Value>>::new());
+    }
+
+    #[test]
+    fn between_predicate_is_inclusive_on_both_ends() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            (1..=12).map(|id| (id, Value::Integer(id * 10))).collect(),
+        );
+
+        let by_primary_key = select_where(
+            &executor,
+            Predicate::Between("id".to_string(), Value::Integer(5), Value::Integer(10)),
+        );
+        assert_eq!(
+            by_primary_key,
+            (5..=10)
+                .map(|id| vec![Value::Integer(id)])
+                .collect::<Vec<Vec<Value>>>()
+        );
+
+        let by_column = select_where(
+            &executor,
+            Predicate::Between("slices".to_string(), Value::Integer(20), Value::Integer(40)),
+        );
+        assert_eq!(
+            by_column,
+            (2..=4)
+                .map(|id| vec![Value::Integer(id)])
+                .collect::<Vec<Vec<Value>>>()
+        );
+    }
+
+    #[test]
+    fn null_never_satisfies_a_predicate() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![(1, Value::Null), (2, Value::Integer(1))],
+        );
+
+        let rows = select_where(
+            &executor,
+            Predicate::Comparison("slices".to_string(), Operator::NotEq, Value::Integer(1)),
+        );
+        assert_eq!(rows, Vec::<Vec<Value>>::new());
+    }
+
+    #[test]
+    fn and_or_predicates_combine_filters() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            (1..=6).map(|id| (id, Value::Integer(id % 3))).collect(),
+        );
+
+        let slices_is = |slices| {
+            Box::new(Predicate::Comparison(
+                "slices".to_string(),
+                Operator::Eq,
+                Value::Integer(slices),
+            ))
+        };
+        let rows = select_where(
+            &executor,
+            Predicate::Or(
+                Box::new(Predicate::And(
+                    Box::new(Predicate::Comparison(
+                        "id".to_string(),
+                        Operator::Gt,
+                        Value::Integer(3),
+                    )),
+                    slices_is(0),
+                )),
+                slices_is(1),
+            ),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(1)],
+                vec![Value::Integer(4)],
+                vec![Value::Integer(6)]
+            ]
+        );
+    }
+
+    #[test]
+    fn null_follows_three_valued_logic() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Null)]);
+
+        let slices_is_one = || {
+            Box::new(Predicate::Comparison(
+                "slices".to_string(),
+                Operator::Eq,
+                Value::Integer(1),
+            ))
+        };
+        let id_is = |id| {
+            Box::new(Predicate::Comparison(
+                "id".to_string(),
+                Operator::Eq,
+                Value::Integer(id),
+            ))
+        };
+
+        let null_and_false = Predicate::Not(Box::new(Predicate::And(slices_is_one(), id_is(2))));
+        assert_eq!(
+            select_where(&executor, null_and_false),
+            vec![vec![Value::Integer(1)]]
+        );
+
+        let null_or_true = Predicate::Or(slices_is_one(), id_is(1));
+        assert_eq!(
+            select_where(&executor, null_or_true),
+            vec![vec![Value::Integer(1)]]
+        );
+
+        let not_null = Predicate::Not(slices_is_one());
+        assert_eq!(select_where(&executor, not_null), Vec::<Vec<Value>>::new());
+    }
+
+    #[test]
+    fn is_null_predicates_check_for_null_directly() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Null),
+                (2, Value::Integer(20)),
+                (3, Value::Null),
+                (4, Value::Integer(40)),
+            ],
+        );
+
+        let is_null = select_where(&executor, Predicate::IsNull("slices".to_string()));
+        assert_eq!(
+            is_null,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]
+        );
+
+        let is_not_null = select_where(&executor, Predicate::IsNotNull("slices".to_string()));
+        assert_eq!(
+            is_not_null,
+            vec![vec![Value::Integer(2)], vec![Value::Integer(4)]]
+        );
+
+        let is_null_or_large = select_where(
+            &executor,
+            Predicate::Or(
+                Box::new(Predicate::IsNull("slices".to_string())),
+                Box::new(Predicate::Comparison(
+                    "slices".to_string(),
+                    Operator::Gt,
+                    Value::Integer(30),
+                )),
+            ),
+        );
+        assert_eq!(
+            is_null_or_large,
+            vec![
+                vec![Value::Integer(1)],
+                vec![Value::Integer(3)],
+                vec![Value::Integer(4)]
+            ]
+        );
+    }
+
+    #[test]
+    fn truncate_removes_every_row_and_restarts_rowids() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            (1..=20).map(|id| (id, Value::Integer(id))).collect(),
+        );
+
+        executor.truncate("apples").unwrap();
+        let rows = executor
+            .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(rows, Vec::<Vec<Value>>::new());
+
+        executor
+            .insert(ast::Insertion::new(
+                "apples",
+                Some(vec!["slices".to_string()]),
+                vec![Value::Integer(5)],
+            ))
+            .unwrap();
+        let rows = executor
+            .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(5)]]);
+    }
+
+    #[test]
+    fn should_fail_to_truncate_a_table_that_does_not_exist() {
+        let mut executor = Executor::<TableMock>::new();
+        assert_eq!(
+            executor.truncate("apples"),
+            Err("no such table: apples".to_string())
+        );
+    }
+
+    #[test]
+    fn added_columns_are_backfilled() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(10))]);
+
+        executor
+            .alter(Alteration::new(
+                "apples",
+                ast::Column::new("seeds", false),
+                Some(Value::Integer(7)),
+            ))
+            .unwrap();
+        executor
+            .alter(Alteration::new(
+                "apples",
+                ast::Column::new("stems", false),
+                None,
+            ))
+            .unwrap();
+        executor
+            .insert(ast::Insertion::new(
+                "apples",
+                None,
+                vec![
+                    Value::Integer(2),
+                    Value::Integer(20),
+                    Value::Integer(3),
+                    Value::Integer(1),
+                ],
+            ))
+            .unwrap();
+
+        let rows = executor
+            .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Integer(10),
+                    Value::Integer(7),
+                    Value::Null
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Integer(20),
+                    Value::Integer(3),
+                    Value::Integer(1)
+                ],
+            ]
+        );
+        assert_eq!(
+            executor.schema("apples").unwrap().columns[2],
+            ast::Column::new("seeds", false)
+        );
+    }
+
+    #[test]
+    fn should_fail_to_add_a_primary_key_or_duplicate_column() {
+        let mut executor = new_executor_with_table(apples_schema());
+
+        let result = executor.alter(Alteration::new(
+            "apples",
+            ast::Column::new("seeds", true),
+            None,
+        ));
+        assert_eq!(result, Err("Cannot add a PRIMARY KEY column".to_string()));
+
+        let result = executor.alter(Alteration::new(
+            "apples",
+            ast::Column::new("slices", false),
+            None,
+        ));
+        assert_eq!(result, Err("duplicate column name: slices".to_string()));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Apple {
+        id: i64,
+        slices: Option<i64>,
+    }
+
+    impl FromRow for Apple {
+        fn from_row(row: &[Value], schema: &TableSchema) -> Result<Apple, String> {
+            Ok(Apple {
+                id: row::get_integer(row, schema, "id")?,
+                slices: row::get_optional_integer(row, schema, "slices")?,
+            })
+        }
+    }
+
+    #[test]
+    fn selected_rows_are_decoded_into_structs() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![(1, Value::Integer(8)), (2, Value::Null)],
+        );
+
+        let apples = executor
+            .query_as::<Apple, _>(ast::Selection::new(
+                "apples",
+                ColumnSet::Names(vec!["slices".to_string(), "id".to_string()]),
+                None,
+            ))
+            .unwrap();
+        assert_eq!(
+            apples,
+            vec![
+                Apple {
+                    id: 1,
+                    slices: Some(8)
+                },
+                Apple {
+                    id: 2,
+                    slices: None
+                },
+            ]
+        );
+
+        let result = executor.query_as::<Apple, _>(ast::Selection::new(
+            "apples",
+            ColumnSet::Names(vec!["slices".to_string()]),
+            None,
+        ));
+        assert_eq!(result, Err("no such column: id".to_string()));
+    }
+
+    #[test]
+    fn exported_table_can_be_imported_again() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(8)),
+                (2, Value::Null),
+                (3, Value::Integer(6)),
+            ],
+        );
+        let select_all = || ast::Selection::new("apples", ColumnSet::WildCard, None);
+        let rows = executor
+            .select(select_all())
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+
+        let json = executor.export_table("apples").unwrap();
+        assert_eq!(
+            json,
+            r#"[{"id":1,"slices":8},{"id":2,"slices":null},{"id":3,"slices":6}]"#
+        );
+
+        executor.truncate("apples").unwrap();
+        assert_eq!(executor.import_table("apples", &json), Ok(3));
+        assert_eq!(
+            executor
+                .select(select_all())
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            rows
+        );
+    }
+
+    #[test]
+    fn unbound_parameters_are_never_stored() {
+        let mut executor = new_executor_with_table(apples_schema());
+        assert_eq!(
+            executor.insert(ast::Insertion::new(
+                "apples",
+                None,
+                vec![Value::Integer(1), Value::Parameter(1)],
+            )),
+            Err("unbound parameter ?1".to_string())
+        );
+        assert_eq!(
+            executor.insert_many(
+                "apples",
+                vec![vec![Value::Parameter(2), Value::Null]].into_iter()
+            ),
+            Err("unbound parameter ?2".to_string())
+        );
+        assert_eq!(executor.row_count("apples"), Ok(0));
+        assert_eq!(executor.export_table("apples"), Ok("[]".to_string()));
+    }
+
+    #[test]
+    fn invalid_import_inserts_nothing() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(8))]);
+
+        let cases = vec![
+            (
+                r#"[{"id":2,"slices":1},{"id":3,"seeds":1}]"#,
+                "no such column: seeds",
+            ),
+            (
+                r#"[{"id":2,"slices":1},{"id":3,"slices":{"many":1}}]"#,
+                "expected an integer, text, boolean or blob for column slices but got {\"many\":1}",
+            ),
+            (
+                r#"[{"id":2,"slices":1},{"id":1,"slices":1}]"#,
+                "duplicate entry: 1",
+            ),
+            (
+                r#"[{"id":2,"slices":1},{"id":2,"slices":1}]"#,
+                "duplicate entry: 2",
+            ),
+        ];
+        for (json, err) in cases {
+            assert_eq!(executor.import_table("apples", json), Err(err.to_string()));
+        }
+
+        assert_eq!(
+            executor.export_table("apples"),
+            Ok(r#"[{"id":1,"slices":8}]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn imports_compare_keys_the_way_the_table_does() {
+        let mut executor = orchards_executor();
+        executor.set_mutation_logging(true);
+        assert_eq!(
+            executor.import_table(
+                "orchards",
+                r#"[{"farm":1,"row":1,"trees":10},{"farm":1,"row":1,"trees":20}]"#
+            ),
+            Err("duplicate entry: (1, 1)".to_string())
+        );
+        assert_eq!(executor.row_count("orchards"), Ok(0));
+
+        let mut fruit = fruit_executor(Collation::NoCase);
+        fruit.set_mutation_logging(true);
+        assert_eq!(
+            fruit.import_table(
+                "fruit",
+                r#"[{"name":"A","variety":"Gala"},{"name":"a","variety":"Fuji"}]"#
+            ),
+            Err("duplicate entry: a".to_string())
+        );
+        assert_eq!(fruit.row_count("fruit"), Ok(0));
+        assert_eq!(executor.mutation_log_iter().count(), 0);
+        assert_eq!(fruit.mutation_log_iter().count(), 0);
+    }
+
+    #[test]
+    fn csv_with_header_is_mapped_by_column_name() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let csv = "slices,id\n8,1\n,2\n\"6\",3\n";
+
+        assert_eq!(executor.import_csv("apples", csv.as_bytes(), true), Ok(3));
+        assert_eq!(
+            executor.export_table("apples"),
+            Ok(r#"[{"id":1,"slices":8},{"id":2,"slices":null},{"id":3,"slices":6}]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn csv_without_header_is_mapped_by_position() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let csv = "1,8\n2,\n";
+
+        assert_eq!(executor.import_csv("apples", csv.as_bytes(), false), Ok(2));
+        assert_eq!(
+            executor.export_table("apples"),
+            Ok(r#"[{"id":1,"slices":8},{"id":2,"slices":null}]"#.to_string())
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_parsed_by_column_type() {
+        let mut executor = new_executor_with_table(TableSchema::new(
+            "fruit",
+            vec![
+                ast::Column::new_with_data_type("name", DataType::Text, true, false),
+                ast::Column::new_with_data_type("ripe", DataType::Boolean, false, false),
+                ast::Column::new_with_data_type("photo", DataType::Blob, false, false),
+            ],
+        ));
+        let csv = "name,ripe,photo\nApple,true,X'4869'\n\"Pear, Bosc\",FALSE,\n";
+
+        assert_eq!(executor.import_csv("fruit", csv.as_bytes(), true), Ok(2));
+        let result_set = executor
+            .query(ast::Selection::new("fruit", ColumnSet::WildCard, None))
+            .unwrap();
+        assert_eq!(
+            result_set.rows,
+            vec![
+                vec![
+                    text("Apple"),
+                    Value::Boolean(true),
+                    Value::Blob(b"Hi".to_vec())
+                ],
+                vec![text("Pear, Bosc"), Value::Boolean(false), Value::Null],
+            ]
+        );
+
+        executor.truncate("fruit").unwrap();
+        assert_eq!(
+            executor.import_csv("fruit", result_set.to_csv().as_bytes(), true),
+            Ok(2)
+        );
+        assert_eq!(
+            executor
+                .query(ast::Selection::new("fruit", ColumnSet::WildCard, None))
+                .map(|result_set| result_set.rows),
+            Ok(result_set.rows)
+        );
+        assert_eq!(
+            executor.import_csv("fruit", "Plum,maybe,\n".as_bytes(), false),
+            Err("line 1, column ripe: expected a boolean but got \"maybe\"".to_string())
+        );
+    }
+
+    #[test]
+    fn csv_with_a_bad_value_reports_its_line_and_column() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let csv = "id,slices\n1,8\n2,many\n";
+
+        assert_eq!(
+            executor.import_csv("apples", csv.as_bytes(), true),
+            Err("line 3, column slices: expected an integer but got \"many\"".to_string())
+        );
+        assert_eq!(executor.export_table("apples"), Ok("[]".to_string()));
+    }
+
+    #[test]
+    fn explain_shows_whether_the_primary_key_is_used() {
+        let executor = new_executor_with_table(apples_schema());
+        let explain = |predicate| {
+            executor.explain(ast::Selection::new(
+                "apples",
+                ColumnSet::WildCard,
+                Some(predicate),
+            ))
+        };
+
+        assert_eq!(
+            explain(Predicate::Comparison(
+                "id".to_string(),
+                Operator::Eq,
+                Value::Integer(1)
+            )),
+            Ok("SEARCH apples USING PRIMARY KEY".to_string())
+        );
+        assert_eq!(
+            explain(Predicate::Comparison(
+                "slices".to_string(),
+                Operator::Eq,
+                Value::Integer(1)
+            )),
+            Ok("SCAN apples".to_string())
+        );
+        assert_eq!(
+            explain(Predicate::IsNull("seeds".to_string())),
+            Err("no such column: seeds".to_string())
+        );
+    }
+
+    #[test]
+    fn explain_shows_a_sort_step_for_order_by() {
+        let executor = new_executor_with_table(apples_schema());
+        let explain = |order_by| {
+            executor.explain(ast::Selection::new_with_order_by(
+                "apples",
+                ColumnSet::WildCard,
+                Some(Predicate::Comparison(
+                    "id".to_string(),
+                    Operator::Eq,
+                    Value::Integer(1),
+                )),
+                order_by,
+            ))
+        };
+
+        assert_eq!(
+            explain(vec![]),
+            Ok("SEARCH apples USING PRIMARY KEY".to_string())
+        );
+        assert_eq!(
+            explain(vec![OrderingTerm::new("slices", SortOrder::Desc, None)]),
+            Ok("SEARCH apples USING PRIMARY KEY\nUSE TEMP B-TREE FOR ORDER BY".to_string())
+        );
+        assert_eq!(
+            explain(vec![OrderingTerm::new("seeds", SortOrder::Asc, None)]),
+            Err("no such column: seeds".to_string())
+        );
+    }
+
+    #[test]
+    fn vacuum_keeps_every_row() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let rows = (1..=100)
+            .map(|id| (id, Value::Integer(id * 2)))
+            .collect::<Vec<(i64, Value)>>();
+        insert_apples(&mut executor, rows.clone());
+
+        executor.vacuum().unwrap();
+        assert_eq!(
+            executor
+                .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            rows.into_iter()
+                .map(|(id, slices)| vec![Value::Integer(id), slices])
+                .collect::<Vec<Vec<Value>>>()
+        );
+    }
+
+    #[test]
+    fn row_count_tracks_inserts_and_truncates() {
+        let mut executor = new_executor_with_table(apples_schema());
+        assert_eq!(executor.row_count("apples"), Ok(0));
+
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(8)),
+                (2, Value::Null),
+                (3, Value::Integer(6)),
+            ],
+        );
+        let result = executor.insert(ast::Insertion::new(
+            "apples",
+            None,
+            vec![Value::Integer(1), Value::Integer(1)],
+        ));
+        assert!(result.is_err());
+        assert_eq!(executor.row_count("apples"), Ok(3));
+
+        executor.truncate("apples").unwrap();
+        assert_eq!(executor.row_count("apples"), Ok(0));
+        assert_eq!(
+            executor.row_count("oranges"),
+            Err("no such table: oranges".to_string())
+        );
+    }
+
+    #[test]
+    fn count_with_a_predicate_counts_matching_rows() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(8)),
+                (2, Value::Null),
+                (3, Value::Integer(6)),
+            ],
+        );
+        let count = |predicate| {
+            executor
+                .select(ast::Selection::new("apples", ColumnSet::Count, predicate))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>()
+        };
+
+        assert_eq!(count(None), vec![vec![Value::Integer(3)]]);
+        assert_eq!(
+            count(Some(Predicate::IsNotNull("slices".to_string()))),
+            vec![vec![Value::Integer(2)]]
+        );
+    }
+
+    #[test]
+    fn insert_returns_the_requested_columns_of_the_inserted_row() {
+        let mut executor = new_executor_with_table(apples_schema());
+
+        let returned = executor.insert(ast::Insertion::new_with_returning(
+            "apples",
+            Some(vec!["slices".to_string()]),
+            vec![Value::Integer(8)],
+            Some(ColumnSet::Names(vec!["id".to_string()])),
+        ));
+        assert_eq!(returned, Ok(vec![vec![Value::Integer(1)]]));
+
+        let returned = executor.insert(ast::Insertion::new_with_returning(
+            "apples",
+            None,
+            vec![Value::Integer(5), Value::Integer(6)],
+            Some(ColumnSet::WildCard),
+        ));
+        assert_eq!(
+            returned,
+            Ok(vec![vec![Value::Integer(5), Value::Integer(6)]])
+        );
+
+        let returned = executor.insert(ast::Insertion::new(
+            "apples",
+            None,
+            vec![Value::Integer(7), Value::Integer(6)],
+        ));
+        assert_eq!(returned, Ok(vec![]));
+
+        let returned = executor.insert(ast::Insertion::new_with_returning(
+            "apples",
+            None,
+            vec![Value::Integer(9), Value::Integer(6)],
+            Some(ColumnSet::Names(vec!["seeds".to_string()])),
+        ));
+        assert_eq!(returned, Err("no such column: seeds".to_string()));
+        assert_eq!(executor.row_count("apples"), Ok(3));
+    }
+
+    #[test]
+    fn like_does_not_backtrack_exponentially() {
+        let text = vec!['a'; 10000];
+        let pattern = "%a%a%a%a%a%a%a%a%a%a%b".chars().collect::<Vec<char>>();
+
+        assert!(!like(&text, &pattern, None));
+        assert!(like(&text, &pattern[..pattern.len() - 1], None));
+    }
+
+    #[test]
+    fn like_matches_wildcards_ignoring_ascii_case() {
+        let mut executor = new_executor_with_table(TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("name", false),
+            ],
+        ));
+        for (id, name) in vec![
+            (1, Value::Text("Granny Smith".to_string())),
+            (2, Value::Text("Gala".to_string())),
+            (3, Value::Text("Fuji".to_string())),
+            (4, Value::Text("100% Honeycrisp".to_string())),
+            (5, Value::Null),
+        ] {
+            insert_apples(&mut executor, vec![(id, name)]);
+        }
+        let like = |pattern: &str, escape: Option<char>| {
+            select_where(
+                &executor,
+                Predicate::Like("name".to_string(), pattern.to_string(), escape),
+            )
+        };
+        let ids = |ids: Vec<i64>| {
+            ids.into_iter()
+                .map(|id| vec![Value::Integer(id)])
+                .collect::<Vec<Vec<Value>>>()
+        };
+
+        assert_eq!(like("g%", None), ids(vec![1, 2]));
+        assert_eq!(like("%SMITH", None), ids(vec![1]));
+        assert_eq!(like("%a%", None), ids(vec![1, 2]));
+        assert_eq!(like("Fu_i", None), ids(vec![3]));
+        assert_eq!(like("G_la", None), ids(vec![2]));
+        assert_eq!(like("Fu_", None), ids(vec![]));
+        assert_eq!(like("%\\%%", Some('\\')), ids(vec![4]));
+        assert_eq!(like("%\\%", Some('\\')), ids(vec![]));
+        assert_eq!(like("%r%n%y%h", None), ids(vec![1]));
+        assert_eq!(like("%a%a%", None), ids(vec![2]));
+        assert_eq!(
+            select_where(
+                &executor,
+                Predicate::Not(Box::new(Predicate::Like(
+                    "name".to_string(),
+                    "G%".to_string(),
+                    None
+                )))
+            ),
+            ids(vec![3, 4])
+        );
+    }
+
+    #[test]
+    fn rolling_back_to_a_savepoint_keeps_earlier_changes() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(8))]);
+
+        executor.savepoint("outer").unwrap();
+        insert_apples(&mut executor, vec![(2, Value::Integer(6))]);
+        executor.savepoint("inner").unwrap();
+        insert_apples(&mut executor, vec![(3, Value::Integer(4))]);
+        executor
+            .alter(Alteration::new(
+                "apples",
+                ast::Column::new("seeds", false),
+                None,
+            ))
+            .unwrap();
+        executor
+            .add_table(
+                table::Table::new(
+                    TableSchema::new("pears", vec![ast::Column::new("id", true)]),
+                    RowTree::new(4, 16, bptree::Serializer::Mock),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        executor.rollback_to("inner").unwrap();
+        assert_eq!(executor.table_names(), vec!["apples".to_string()]);
+        assert_eq!(executor.schema("apples"), Some(apples_schema()));
+        let rows = executor
+            .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Integer(1), Value::Integer(8)],
+                vec![Value::Integer(2), Value::Integer(6)],
+            ]
+        );
+
+        // The inner savepoint stays open after rolling back to it
+        insert_apples(&mut executor, vec![(4, Value::Integer(2))]);
+        executor.rollback_to("INNER").unwrap();
+        assert_eq!(executor.row_count("apples"), Ok(2));
+
+        insert_apples(&mut executor, vec![(5, Value::Integer(2))]);
+        executor.release_savepoint("inner").unwrap();
+        executor.release_savepoint("outer").unwrap();
+        assert_eq!(executor.row_count("apples"), Ok(3));
+    }
+
+    #[test]
+    fn unknown_savepoints_are_rejected() {
+        let mut executor = new_executor_with_table(apples_schema());
+        executor.savepoint("sp1").unwrap();
+        executor.release_savepoint("sp1").unwrap();
+
+        assert_eq!(
+            executor.rollback_to("sp1"),
+            Err("no such savepoint: sp1".to_string())
+        );
+        assert_eq!(
+            executor.release_savepoint("sp2"),
+            Err("no such savepoint: sp2".to_string())
+        );
+    }
+
+    fn orchards_executor() -> Executor<table::Table<RowTree>> {
+        new_executor_with_table(TableSchema::new_with_primary_key(
+            "orchards",
+            vec![
+                ast::Column::new("farm", false),
+                ast::Column::new("row", false),
+                ast::Column::new("trees", false),
+            ],
+            vec!["farm".to_string(), "row".to_string()],
+        ))
+    }
+
+    fn insert_orchard(
+        executor: &mut Executor<table::Table<RowTree>>,
+        farm: i64,
+        row: i64,
+        trees: i64,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        executor.insert(ast::Insertion::new(
+            "orchards",
+            None,
+            vec![
+                Value::Integer(farm),
+                Value::Integer(row),
+                Value::Integer(trees),
+            ],
+        ))
+    }
+
+    #[test]
+    fn composite_primary_keys_are_unique_across_all_of_their_columns() {
+        let mut executor = orchards_executor();
+        insert_orchard(&mut executor, 1, 1, 10).unwrap();
+        insert_orchard(&mut executor, 1, 2, 20).unwrap();
+        insert_orchard(&mut executor, 2, 1, 30).unwrap();
+
+        assert_eq!(
+            insert_orchard(&mut executor, 1, 2, 40),
+            Err("duplicate entry: (1, 2)".to_string())
+        );
+        assert_eq!(executor.row_count("orchards"), Ok(3));
+        assert_eq!(
+            executor.schema("orchards").map(|schema| schema.primary_key),
+            Some(vec!["farm".to_string(), "row".to_string()])
+        );
+    }
+
+    #[test]
+    fn rows_are_looked_up_by_every_column_of_a_composite_primary_key() {
+        let mut executor = orchards_executor();
+        insert_orchard(&mut executor, 2, 1, 30).unwrap();
+        insert_orchard(&mut executor, 1, 2, 20).unwrap();
+        insert_orchard(&mut executor, 1, 1, 10).unwrap();
+
+        let selection = ast::Selection::new(
+            "orchards",
+            ColumnSet::Names(vec!["trees".to_string()]),
+            Some(Predicate::And(
+                Box::new(Predicate::Comparison(
+                    "farm".to_string(),
+                    Operator::Eq,
+                    Value::Integer(1),
+                )),
+                Box::new(Predicate::Comparison(
+                    "row".to_string(),
+                    Operator::Eq,
+                    Value::Integer(2),
+                )),
+            )),
+        );
+        let rows = executor
+            .select(selection.clone())
+            .unwrap()
+            .collect::<Vec<Vec<Value>>>();
+        assert_eq!(rows, vec![vec![Value::Integer(20)]]);
+        assert_eq!(
+            executor.explain(selection),
+            Ok("SEARCH orchards USING PRIMARY KEY".to_string())
+        );
+
+        let first_column_only = ast::Selection::new(
+            "orchards",
+            ColumnSet::WildCard,
+            Some(Predicate::Comparison(
+                "farm".to_string(),
+                Operator::Eq,
+                Value::Integer(1),
+            )),
+        );
+        assert_eq!(
+            executor.explain(first_column_only),
+            Ok("SCAN orchards".to_string())
+        );
+
+        let rows = executor
+            .select(ast::Selection::new("orchards", ColumnSet::WildCard, None))
+            .unwrap()
+            .map(|row| (row[0].clone(), row[1].clone()))
+            .collect::<Vec<(Value, Value)>>();
+        assert_eq!(
+            rows,
+            vec![
+                (Value::Integer(1), Value::Integer(1)),
+                (Value::Integer(1), Value::Integer(2)),
+                (Value::Integer(2), Value::Integer(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_are_filtered_on_a_boolean_column() {
+        let mut executor = new_executor_with_table(TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("ripe", false),
+            ],
+        ));
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Boolean(true)),
+                (2, Value::Boolean(false)),
+                (3, Value::Null),
+                (4, Value::Boolean(true)),
+            ],
+        );
+        let ripe_is = |operator, value| {
+            select_where(
+                &executor,
+                Predicate::Comparison("ripe".to_string(), operator, value),
+            )
+        };
+
+        assert_eq!(
+            ripe_is(Operator::Eq, Value::Boolean(true)),
+            vec![vec![Value::Integer(1)], vec![Value::Integer(4)]]
+        );
+        assert_eq!(
+            ripe_is(Operator::Lt, Value::Boolean(true)),
+            vec![vec![Value::Integer(2)]]
+        );
+        assert_eq!(
+            ripe_is(Operator::Eq, Value::Integer(1)),
+            Vec::<Vec<Value>>::new()
+        );
+    }
+
+    #[test]
+    fn selections_give_up_once_they_exceed_their_budget() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            (1..=20).map(|id| (id, Value::Integer(id))).collect(),
+        );
+        let selection = || {
+            ast::Selection::new(
+                "apples",
+                ColumnSet::WildCard,
+                Some(Predicate::Comparison(
+                    "slices".to_string(),
+                    Operator::Gt,
+                    Value::Integer(100),
+                )),
+            )
+        };
+        let max_scanned_rows = |max_scanned_rows| ExecOptions {
+            max_scanned_rows: Some(max_scanned_rows),
+            ..ExecOptions::default()
+        };
+
+        assert_eq!(
+            executor.select_with_options(selection(), max_scanned_rows(5)),
+            Err("query budget exceeded".to_string())
+        );
+        assert_eq!(
+            executor.select_with_options(selection(), max_scanned_rows(20)),
+            Ok(vec![])
+        );
+        assert_eq!(
+            executor.select_with_options(
+                selection(),
+                ExecOptions {
+                    deadline: Some(Instant::now()),
+                    ..ExecOptions::default()
+                }
+            ),
+            Err("query budget exceeded".to_string())
+        );
+        assert_eq!(
+            executor.select_with_options(selection(), ExecOptions::default()),
+            Ok(vec![])
+        );
+    }
+
+    /// Tree that counts how many rows have been read out of it
+    #[derive(Clone)]
+    struct CountingTree {
+        rows: RowTree,
+        num_read: Rc<Cell<usize>>,
+    }
+
+    impl table::BPTree for CountingTree {
+        fn insert(&mut self, key: table::Key, value: Vec<Value>) -> Result<(), String> {
+            table::BPTree::insert(&mut self.rows, key, value)
+        }
+
+        fn clear(&mut self) {
+            table::BPTree::clear(&mut self.rows)
+        }
+
+        fn compact(&mut self) {
+            table::BPTree::compact(&mut self.rows)
+        }
+
+        fn load(&mut self, entries: Vec<(table::Key, Vec<Value>)>) -> Result<(), String> {
+            table::BPTree::load(&mut self.rows, entries)
+        }
+
+        fn get(&self, key: &table::Key) -> Option<Vec<Value>> {
+            table::BPTree::get(&self.rows, key)
+        }
+
+        fn range(&self, low: &table::Key, high: &table::Key) -> Vec<Vec<Value>> {
+            table::BPTree::range(&self.rows, low, high)
+        }
+    }
+
+    impl IntoIterator for CountingTree {
+        type Item = Vec<Value>;
+        type IntoIter = Box<dyn Iterator<Item = Vec<Value>>>;
+        fn into_iter(self) -> Self::IntoIter {
+            let num_read = self.num_read;
+            Box::new(
+                self.rows
+                    .into_iter()
+                    .inspect(move |_| num_read.set(num_read.get() + 1)),
+            )
+        }
+    }
+
+    #[test]
+    fn scans_stop_reading_rows_once_they_exceed_their_budget() {
+        let num_read = Rc::new(Cell::new(0));
+        let rows = CountingTree {
+            rows: RowTree::new(4, 16, bptree::Serializer::Mock),
+            num_read: num_read.clone(),
+        };
+        let mut executor = Executor::new();
+        executor
+            .add_table(table::Table::new(apples_schema(), rows).unwrap())
+            .unwrap();
+        executor
+            .insert_many(
+                "apples",
+                (1..=1000).map(|id| vec![Value::Integer(id), Value::Integer(id)]),
+            )
+            .unwrap();
+        num_read.set(0);
+
+        assert_eq!(
+            executor.select_with_options(
+                ast::Selection::new("apples", ColumnSet::WildCard, None),
+                ExecOptions {
+                    max_scanned_rows: Some(10),
+                    ..ExecOptions::default()
+                }
+            ),
+            Err("query budget exceeded".to_string())
+        );
+        // the row that goes over the budget is read, but none after it
+        assert_eq!(num_read.get(), 11);
+    }
+
+    #[test]
+    fn savepoints_only_copy_the_tables_changed_after_them() {
+        let mut executor = Executor::new();
+        let mut num_reads = HashMap::new();
+        for table_name in &["apples", "pears"] {
+            let num_read = Rc::new(Cell::new(0));
+            let rows = CountingTree {
+                rows: RowTree::new(4, 16, bptree::Serializer::Mock),
+                num_read: num_read.clone(),
+            };
+            let mut schema = apples_schema();
+            schema.name = table_name.to_string();
+            executor
+                .add_table(table::Table::new(schema, rows).unwrap())
+                .unwrap();
+            executor
+                .insert_many(
+                    table_name,
+                    (1..=100).map(|id| vec![Value::Integer(id), Value::Null]),
+                )
+                .unwrap();
+            num_reads.insert(table_name.to_string(), num_read);
+        }
+        let num_read = |table_name: &str| num_reads[table_name].get();
+
+        executor.savepoint("outer").unwrap();
+        executor.savepoint("inner").unwrap();
+        assert_eq!((num_read("apples"), num_read("pears")), (0, 0));
+
+        executor
+            .insert(ast::Insertion::new(
+                "apples",
+                None,
+                vec![Value::Integer(101), Value::Null],
+            ))
+            .unwrap();
+        executor
+            .insert(ast::Insertion::new(
+                "apples",
+                None,
+                vec![Value::Integer(102), Value::Null],
+            ))
+            .unwrap();
+        // copied once for both savepoints, before the first insert
+        assert_eq!((num_read("apples"), num_read("pears")), (100, 0));
+
+        executor.rollback_to("outer").unwrap();
+        assert_eq!(executor.row_count("apples"), Ok(100));
+        assert_eq!(executor.row_count("pears"), Ok(100));
+        assert_eq!(num_read("pears"), 0);
+    }
+
+    fn farms_executor(on_delete: ForeignKeyAction) -> Executor<table::Table<RowTree>> {
+        let mut executor = new_executor_with_table(TableSchema::new(
+            "farms",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("acres", false),
+            ],
+        ));
+        let mut trees = TableSchema::new(
+            "trees",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("farm", false),
+            ],
+        );
+        trees.foreign_keys = vec![ast::ForeignKey::new(
+            vec!["farm".to_string()],
+            "farms",
+            vec!["id".to_string()],
+            on_delete,
+        )];
+        let rows = RowTree::new(4, 16, bptree::Serializer::Mock);
+        executor
+            .add_table(table::Table::new(trees, rows).unwrap())
+            .unwrap();
+
+        executor
+    }
+
+    fn insert_into(
+        executor: &mut Executor<table::Table<RowTree>>,
+        table_name: &str,
+        values: Vec<Value>,
+    ) -> Result<Vec<Vec<Value>>, String> {
+        executor.insert(ast::Insertion::new(table_name, None, values))
+    }
+
+    #[test]
+    fn insert_many_matches_inserting_rows_one_at_a_time() {
+        let rows = vec![
+            vec![Value::Integer(5), Value::Integer(50)],
+            vec![Value::Null, Value::Null],
+            vec![Value::Integer(2), Value::Integer(20)],
+        ];
+        let mut one_at_a_time = new_executor_with_table(apples_schema());
+        for row in rows.clone() {
+            insert_into(&mut one_at_a_time, "apples", row).unwrap();
+        }
+        let mut together = new_executor_with_table(apples_schema());
+        let all_rows = |executor: &Executor<table::Table<RowTree>>| {
+            executor
+                .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>()
+        };
+
+        assert_eq!(together.insert_many("apples", rows.into_iter()), Ok(3));
+        assert_eq!(all_rows(&together), all_rows(&one_at_a_time));
+        assert_eq!(together.row_count("apples"), Ok(3));
+        assert_eq!(
+            together.insert_many(
+                "apples",
+                vec![vec![Value::Null, Value::Integer(70)]].into_iter()
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            select_where(
+                &together,
+                Predicate::Comparison("slices".to_string(), Operator::Eq, Value::Integer(70))
+            ),
+            vec![vec![Value::Integer(7)]]
+        );
+        assert_eq!(
+            together.insert_many("apples", vec![vec![Value::Integer(8)]].into_iter()),
+            Err("table apples has 2 columns but 1 values were supplied".to_string())
+        );
+        assert_eq!(
+            together.insert_many("oranges", vec![].into_iter()),
+            Err("no such table: oranges".to_string())
+        );
+    }
+
+    #[test]
+    fn insert_many_inserts_nothing_if_a_row_breaks_a_constraint() {
+        let mut executor = farms_executor(ForeignKeyAction::Restrict);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(40)],
+        )
+        .unwrap();
+
+        assert_eq!(
+            executor.insert_many(
+                "trees",
+                vec![
+                    vec![Value::Integer(1), Value::Integer(1)],
+                    vec![Value::Integer(2), Value::Integer(2)],
+                ]
+                .into_iter()
+            ),
+            Err("FOREIGN KEY constraint failed".to_string())
+        );
+        assert_eq!(
+            executor.insert_many(
+                "trees",
+                vec![
+                    vec![Value::Integer(1), Value::Integer(1)],
+                    vec![Value::Integer(1), Value::Null],
+                ]
+                .into_iter()
+            ),
+            Err("duplicate entry: 1".to_string())
+        );
+        assert_eq!(executor.row_count("trees"), Ok(0));
+
+        executor.set_read_only(true);
+        assert_eq!(
+            executor.insert_many("trees", vec![].into_iter()),
+            Err("attempt to write a readonly database".to_string())
+        );
+    }
+
+    #[test]
+    fn migrate_adds_the_columns_a_table_is_missing() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(4))]);
+        let mut desired = apples_schema();
+        desired.columns.push(ast::Column::new_with_data_type(
+            "variety",
+            DataType::Text,
+            false,
+            false,
+        ));
+
+        assert_eq!(
+            executor.diff_schema(&desired),
+            Ok(vec![SchemaChange::AddColumn(desired.columns[2].clone())])
+        );
+        assert_eq!(executor.migrate(&desired), Ok(()));
+        assert_eq!(executor.schema("apples"), Some(desired.clone()));
+        assert_eq!(executor.diff_schema(&desired), Ok(vec![]));
+        assert_eq!(
+            executor
+                .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![vec![Value::Integer(1), Value::Integer(4), Value::Null]]
+        );
+
+        let mut narrower = apples_schema();
+        narrower.columns[1].data_type = DataType::Text;
+        narrower.columns.push(ast::Column::new("seeds", false));
+        assert_eq!(
+            executor.migrate(&narrower),
+            Err(
+                "cannot change the type of column slices of table apples from Integer to Text"
+                    .to_string()
+            )
+        );
+        narrower.columns[1].data_type = DataType::Integer;
+        assert_eq!(
+            executor.migrate(&narrower),
+            Err("cannot drop column variety of table apples".to_string())
+        );
+        assert_eq!(executor.schema("apples"), Some(desired));
+        assert_eq!(
+            executor.diff_schema(&TableSchema::new("oranges", vec![])),
+            Err("no such table: oranges".to_string())
+        );
+    }
+
+    #[test]
+    fn foreign_keys_must_refer_to_an_existing_row() {
+        let mut executor = farms_executor(ForeignKeyAction::Restrict);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(40)],
+        )
+        .unwrap();
+
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "trees",
+                vec![Value::Integer(1), Value::Integer(1)]
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "trees",
+                vec![Value::Integer(2), Value::Integer(2)]
+            ),
+            Err("FOREIGN KEY constraint failed".to_string())
+        );
+        assert_eq!(
+            insert_into(&mut executor, "trees", vec![Value::Integer(3), Value::Null]),
+            Ok(vec![])
+        );
+        assert_eq!(executor.row_count("trees"), Ok(2));
+        assert_eq!(
+            executor.import_table("trees", r#"[{"id": 4, "farm": 1}, {"id": 5, "farm": 3}]"#),
+            Err("FOREIGN KEY constraint failed".to_string())
+        );
+        assert_eq!(executor.row_count("trees"), Ok(2));
+
+        let references = |parent_table: &str, parent_column: &str| {
+            let mut schema = TableSchema::new(
+                "stumps",
+                vec![
+                    ast::Column::new("id", true),
+                    ast::Column::new("farm", false),
+                ],
+            );
+            schema.foreign_keys = vec![ast::ForeignKey::new(
+                vec!["farm".to_string()],
+                parent_table,
+                vec![parent_column.to_string()],
+                ForeignKeyAction::Restrict,
+            )];
+            table::Table::new(schema, RowTree::new(4, 16, bptree::Serializer::Mock)).unwrap()
+        };
+        assert_eq!(
+            executor.add_table(references("farms", "acres")),
+            Err("foreign key mismatch - \"stumps\" referencing \"farms\"".to_string())
+        );
+        assert_eq!(
+            executor.add_table(references("barns", "id")),
+            Err("no such table: barns".to_string())
+        );
+    }
+
+    #[test]
+    fn truncating_a_referenced_table_restricts_or_cascades() {
+        for on_delete in vec![ForeignKeyAction::Restrict, ForeignKeyAction::Cascade] {
+            let mut executor = farms_executor(on_delete);
+            insert_into(
+                &mut executor,
+                "farms",
+                vec![Value::Integer(1), Value::Integer(40)],
+            )
+            .unwrap();
+            insert_into(
+                &mut executor,
+                "trees",
+                vec![Value::Integer(1), Value::Integer(1)],
+            )
+            .unwrap();
+            insert_into(&mut executor, "trees", vec![Value::Integer(2), Value::Null]).unwrap();
+
+            let result = executor.truncate("farms");
+            let trees = executor
+                .select(ast::Selection::new("trees", ColumnSet::WildCard, None))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>();
+            match on_delete {
+                ForeignKeyAction::Restrict => {
+                    assert_eq!(result, Err("FOREIGN KEY constraint failed".to_string()));
+                    assert_eq!(executor.row_count("farms"), Ok(1));
+                    assert_eq!(trees.len(), 2);
+                }
+                ForeignKeyAction::Cascade => {
+                    assert_eq!(result, Ok(()));
+                    assert_eq!(executor.row_count("farms"), Ok(0));
+                    assert_eq!(trees, vec![vec![Value::Integer(2), Value::Null]]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rowids_removed_by_a_cascade_are_not_handed_out_again() {
+        let mut executor = farms_executor(ForeignKeyAction::Cascade);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(40)],
+        )
+        .unwrap();
+        insert_into(&mut executor, "trees", vec![Value::Null, Value::Null]).unwrap();
+        insert_into(&mut executor, "trees", vec![Value::Null, Value::Integer(1)]).unwrap();
+
+        executor.truncate("farms").unwrap();
+        insert_into(&mut executor, "trees", vec![Value::Null, Value::Null]).unwrap();
+        assert_eq!(
+            executor
+                .select(ast::Selection::new("trees", ColumnSet::WildCard, None))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Integer(1), Value::Null],
+                vec![Value::Integer(3), Value::Null],
+            ]
+        );
+    }
+
+    fn mutation(kind: MutationKind, table_name: &str, row: Vec<Value>) -> Mutation {
+        Mutation {
+            kind,
+            table_name: table_name.to_string(),
+            key: vec![row[0].clone()],
+            row,
+        }
+    }
+
+    #[test]
+    fn inserted_and_deleted_rows_are_logged_in_order() {
+        let mut executor = farms_executor(ForeignKeyAction::Cascade);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(40)],
+        )
+        .unwrap();
+        assert_eq!(executor.mutation_log_iter().count(), 0);
+
+        executor.set_mutation_logging(true);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Null, Value::Integer(15)],
+        )
+        .unwrap();
+        executor
+            .insert_many(
+                "trees",
+                vec![
+                    vec![Value::Integer(1), Value::Integer(1)],
+                    vec![Value::Integer(2), Value::Null],
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        executor.truncate("farms").unwrap();
+
+        assert_eq!(
+            executor
+                .mutation_log_iter()
+                .cloned()
+                .collect::<Vec<Mutation>>(),
+            vec![
+                mutation(
+                    MutationKind::Insert,
+                    "farms",
+                    vec![Value::Integer(2), Value::Integer(15)]
+                ),
+                mutation(
+                    MutationKind::Insert,
+                    "trees",
+                    vec![Value::Integer(1), Value::Integer(1)]
+                ),
+                mutation(
+                    MutationKind::Insert,
+                    "trees",
+                    vec![Value::Integer(2), Value::Null]
+                ),
+                mutation(
+                    MutationKind::Delete,
+                    "trees",
+                    vec![Value::Integer(1), Value::Integer(1)]
+                ),
+                mutation(
+                    MutationKind::Delete,
+                    "farms",
+                    vec![Value::Integer(1), Value::Integer(40)]
+                ),
+                mutation(
+                    MutationKind::Delete,
+                    "farms",
+                    vec![Value::Integer(2), Value::Integer(15)]
+                ),
+            ]
+        );
+
+        executor.clear_mutation_log();
+        assert_eq!(executor.mutation_log_iter().count(), 0);
+        insert_into(&mut executor, "farms", vec![Value::Integer(3), Value::Null]).unwrap();
+        assert_eq!(executor.mutation_log_iter().count(), 1);
+        executor.set_mutation_logging(false);
+        insert_into(&mut executor, "farms", vec![Value::Integer(4), Value::Null]).unwrap();
+        assert_eq!(executor.mutation_log_iter().count(), 0);
+    }
+
+    #[test]
+    fn rolled_back_and_failed_mutations_are_not_logged() {
+        let mut executor = farms_executor(ForeignKeyAction::Restrict);
+        executor.set_mutation_logging(true);
+        insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(40)],
+        )
+        .unwrap();
+
+        executor.savepoint("planting").unwrap();
+        insert_into(
+            &mut executor,
+            "trees",
+            vec![Value::Integer(1), Value::Integer(1)],
+        )
+        .unwrap();
+        assert!(executor.truncate("farms").is_err());
+        assert!(insert_into(
+            &mut executor,
+            "farms",
+            vec![Value::Integer(1), Value::Integer(10)]
+        )
+        .is_err());
+        assert_eq!(executor.mutation_log_iter().count(), 2);
+        executor.rollback_to("planting").unwrap();
+        executor.release_savepoint("planting").unwrap();
+
+        assert_eq!(
+            executor
+                .mutation_log_iter()
+                .cloned()
+                .collect::<Vec<Mutation>>(),
+            vec![mutation(
+                MutationKind::Insert,
+                "farms",
+                vec![Value::Integer(1), Value::Integer(40)]
+            )]
+        );
+    }
+
+    #[test]
+    fn rows_failing_a_check_constraint_are_rejected() {
+        let mut schema = TableSchema::new(
+            "people",
+            vec![ast::Column::new("id", true), ast::Column::new("age", false)],
+        );
+        schema.checks = vec![Predicate::Comparison(
+            "age".to_string(),
+            Operator::GtEq,
+            Value::Integer(0),
+        )];
+        let mut executor = new_executor_with_table(schema);
+
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "people",
+                vec![Value::Integer(1), Value::Integer(30)]
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "people",
+                vec![Value::Integer(2), Value::Integer(-1)]
+            ),
+            Err("CHECK constraint failed: people".to_string())
+        );
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "people",
+                vec![Value::Integer(3), Value::Null]
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            executor.insert(ast::Insertion::new(
+                "people",
+                Some(vec!["id".to_string()]),
+                vec![Value::Integer(4)],
+            )),
+            Ok(vec![])
+        );
+        assert_eq!(
+            executor.import_table("people", r#"[{"id": 5, "age": 7}, {"id": 6, "age": -7}]"#),
+            Err("CHECK constraint failed: people".to_string())
+        );
+        assert_eq!(executor.row_count("people"), Ok(3));
+        assert_eq!(
+            executor.schema("people").map(|schema| schema.checks.len()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn nulls_are_sorted_first_or_last_regardless_of_direction() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(20)),
+                (2, Value::Null),
+                (3, Value::Integer(10)),
+                (4, Value::Integer(20)),
+            ],
+        );
+        let ids_ordered_by = |order_by: Vec<OrderingTerm>| {
+            executor
+                .select(ast::Selection::new_with_order_by(
+                    "apples",
+                    ColumnSet::Names(vec!["id".to_string()]),
+                    None,
+                    order_by,
+                ))
+                .unwrap()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let ids = |ids: Vec<i64>| ids.into_iter().map(Value::Integer).collect::<Vec<Value>>();
+        let slices = |order, nulls| OrderingTerm::new("slices", order, nulls);
+        let id_desc = OrderingTerm::new("id", SortOrder::Desc, None);
+
+        assert_eq!(
+            ids_ordered_by(vec![slices(SortOrder::Asc, None)]),
+            ids(vec![2, 3, 1, 4])
+        );
+        assert_eq!(
+            ids_ordered_by(vec![slices(SortOrder::Desc, None), id_desc.clone()]),
+            ids(vec![4, 1, 3, 2])
+        );
+        assert_eq!(
+            ids_ordered_by(vec![
+                slices(SortOrder::Desc, Some(NullsOrder::Last)),
+                id_desc.clone()
+            ]),
+            ids(vec![4, 1, 3, 2])
+        );
+        assert_eq!(
+            ids_ordered_by(vec![
+                slices(SortOrder::Desc, Some(NullsOrder::First)),
+                id_desc
+            ]),
+            ids(vec![2, 4, 1, 3])
+        );
+        assert_eq!(
+            ids_ordered_by(vec![slices(SortOrder::Asc, Some(NullsOrder::Last))]),
+            ids(vec![3, 1, 4, 2])
+        );
+        assert_eq!(
+            executor
+                .select(ast::Selection::new_with_order_by(
+                    "apples",
+                    ColumnSet::WildCard,
+                    None,
+                    vec![OrderingTerm::new("seeds", SortOrder::Asc, None)],
+                ))
+                .err(),
+            Some("no such column: seeds".to_string())
+        );
+    }
+
+    fn fruit_executor(name_collation: Collation) -> Executor<table::Table<RowTree>> {
+        let mut name = ast::Column::new_with_data_type("name", DataType::Text, true, false);
+        name.collation = name_collation;
+        let mut variety = ast::Column::new_with_data_type("variety", DataType::Text, false, false);
+        variety.collation = Collation::NoCase;
+        new_executor_with_table(TableSchema::new("fruit", vec![name, variety]))
+    }
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    #[test]
+    fn nocase_columns_sort_and_compare_without_regard_to_case() {
+        let mut executor = fruit_executor(Collation::Binary);
+        for (name, variety) in vec![
+            ("b", "Gala"),
+            ("A", "fuji"),
+            ("a", "Fuji"),
+            ("C", "honeycrisp"),
+        ] {
+            insert_into(&mut executor, "fruit", vec![text(name), text(variety)]).unwrap();
+        }
+        let names = |executor: &Executor<table::Table<RowTree>>, selection: ast::Selection| {
+            executor
+                .select(selection)
+                .unwrap()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let ordered_by = |term: OrderingTerm| {
+            ast::Selection::new_with_order_by(
+                "fruit",
+                ColumnSet::Names(vec!["name".to_string()]),
+                None,
+                vec![term, OrderingTerm::new("name", SortOrder::Asc, None)],
+            )
+        };
+        let mut name_nocase = OrderingTerm::new("name", SortOrder::Asc, None);
+        name_nocase.collation = Some(Collation::NoCase);
+
+        assert_eq!(
+            names(
+                &executor,
+                ordered_by(OrderingTerm::new("name", SortOrder::Asc, None))
+            ),
+            vec![text("A"), text("C"), text("a"), text("b")]
+        );
+        assert_eq!(
+            names(&executor, ordered_by(name_nocase)),
+            vec![text("A"), text("a"), text("b"), text("C")]
+        );
+        assert_eq!(
+            names(
+                &executor,
+                ordered_by(OrderingTerm::new("variety", SortOrder::Desc, None))
+            ),
+            vec![text("C"), text("b"), text("A"), text("a")]
+        );
+        assert_eq!(
+            names(
+                &executor,
+                ast::Selection::new(
+                    "fruit",
+                    ColumnSet::Names(vec!["name".to_string()]),
+                    Some(Predicate::Comparison(
+                        "variety".to_string(),
+                        Operator::Eq,
+                        text("FUJI")
+                    )),
+                )
+            ),
+            vec![text("A"), text("a")]
+        );
+        assert_eq!(
+            names(
+                &executor,
+                ast::Selection::new(
+                    "fruit",
+                    ColumnSet::Names(vec!["name".to_string()]),
+                    Some(Predicate::In(
+                        "name".to_string(),
+                        vec![text("B"), text("c")]
+                    )),
+                )
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn nocase_primary_keys_treat_case_variants_as_the_same_key() {
+        let mut executor = fruit_executor(Collation::NoCase);
+        insert_into(&mut executor, "fruit", vec![text("Apple"), text("Gala")]).unwrap();
+
+        assert_eq!(
+            insert_into(&mut executor, "fruit", vec![text("aPPLE"), text("Fuji")]),
+            Err("duplicate entry: apple".to_string())
+        );
+        assert_eq!(
+            executor
+                .select(ast::Selection::new(
+                    "fruit",
+                    ColumnSet::WildCard,
+                    Some(Predicate::Comparison(
+                        "name".to_string(),
+                        Operator::Eq,
+                        text("APPLE")
+                    )),
+                ))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![vec![text("Apple"), text("Gala")]]
+        );
+
+        let mut executor = fruit_executor(Collation::Binary);
+        insert_into(&mut executor, "fruit", vec![text("Apple"), text("Gala")]).unwrap();
+        assert_eq!(
+            insert_into(&mut executor, "fruit", vec![text("aPPLE"), text("Fuji")]),
+            Ok(vec![])
+        );
+    }
+
+    #[test]
+    fn numbers_cast_to_text_compare_and_sort_as_text() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(10)),
+                (2, Value::Integer(9)),
+                (3, Value::Null),
+            ],
+        );
+        let slices_as_text =
+            || Expression::cast(Expression::Column("slices".to_string()), DataType::Text);
+        let selection = ast::Selection::new(
+            "apples",
+            ColumnSet::Expressions(vec![Expression::Column("id".to_string()), slices_as_text()]),
+            Some(Predicate::ExpressionComparison(
+                slices_as_text(),
+                Operator::Lt,
+                Value::Text("9".to_string()),
+            )),
+        );
+
+        assert_eq!(
+            executor
+                .select(selection.clone())
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![vec![Value::Integer(1), Value::Text("10".to_string())]]
+        );
+        assert_eq!(
+            executor.describe(&selection),
+            Ok(vec![
+                ("id".to_string(), DataType::Integer),
+                ("CAST(slices AS TEXT)".to_string(), DataType::Text),
+            ])
+        );
+        assert_eq!(
+            executor
+                .select(ast::Selection::new(
+                    "apples",
+                    ColumnSet::Expressions(vec![slices_as_text()]),
+                    None,
+                ))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Text("10".to_string())],
+                vec![Value::Text("9".to_string())],
+                vec![Value::Null],
+            ]
+        );
+        assert_eq!(
+            executor
+                .select(ast::Selection::new(
+                    "apples",
+                    ColumnSet::Expressions(vec![Expression::cast(
+                        Expression::Column("seeds".to_string()),
+                        DataType::Text
+                    )]),
+                    None,
+                ))
+                .err(),
+            Some("no such column: seeds".to_string())
+        );
+    }
+
+    #[test]
+    fn text_that_is_not_a_number_casts_to_null() {
+        let mut executor = fruit_executor(Collation::Binary);
+        for (name, variety) in vec![("a", "12"), ("b", "twelve"), ("c", " 7 ")] {
+            insert_into(&mut executor, "fruit", vec![text(name), text(variety)]).unwrap();
+        }
+        let variety_as_integer =
+            || Expression::cast(Expression::Column("variety".to_string()), DataType::Integer);
+
+        assert_eq!(
+            executor
+                .select(ast::Selection::new(
+                    "fruit",
+                    ColumnSet::Expressions(vec![variety_as_integer()]),
+                    None,
+                ))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Integer(12)],
+                vec![Value::Null],
+                vec![Value::Integer(7)],
+            ]
+        );
+        assert_eq!(
+            executor
+                .select(ast::Selection::new(
+                    "fruit",
+                    ColumnSet::Names(vec!["name".to_string()]),
+                    Some(Predicate::ExpressionComparison(
+                        variety_as_integer(),
+                        Operator::Gt,
+                        Value::Integer(8),
+                    )),
+                ))
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>(),
+            vec![vec![text("a")]]
+        );
+    }
+
+    #[test]
+    fn query_returns_the_selected_rows_with_their_column_names() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![(1, Value::Integer(4)), (2, Value::Null)],
+        );
+
+        let result_set = executor
+            .query(ast::Selection::new("apples", ColumnSet::WildCard, None))
+            .unwrap();
+        assert_eq!(
+            result_set,
+            ResultSet::new(
+                vec!["id".to_string(), "slices".to_string()],
+                vec![
+                    vec![Value::Integer(1), Value::Integer(4)],
+                    vec![Value::Integer(2), Value::Null],
+                ]
+            )
+        );
+        assert_eq!(
+            executor
+                .query(ast::Selection::new("apples", ColumnSet::Count, None))
+                .unwrap()
+                .to_csv(),
+            "count(*)\n2\n"
+        );
+    }
+
+    #[test]
+    fn comparison_mode_decides_where_order_by_puts_nulls() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(
+            &mut executor,
+            vec![
+                (1, Value::Integer(20)),
+                (2, Value::Null),
+                (3, Value::Integer(10)),
+            ],
+        );
+        let ids_ordered_by = |executor: &Executor<table::Table<RowTree>>, term| {
+            executor
+                .select(ast::Selection::new_with_order_by(
+                    "apples",
+                    ColumnSet::Names(vec!["id".to_string()]),
+                    None,
+                    vec![term],
+                ))
+                .unwrap()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let ids = |ids: Vec<i64>| ids.into_iter().map(Value::Integer).collect::<Vec<Value>>();
+        let slices = |order, nulls| OrderingTerm::new("slices", order, nulls);
+
+        assert_eq!(executor.comparison_mode(), ComparisonMode::Sqlite);
+        assert_eq!(
+            ids_ordered_by(&executor, slices(SortOrder::Asc, None)),
+            ids(vec![2, 3, 1])
+        );
+        assert_eq!(
+            ids_ordered_by(&executor, slices(SortOrder::Desc, None)),
+            ids(vec![1, 3, 2])
+        );
+
+        executor.set_comparison_mode(ComparisonMode::NullsGreatest);
+        assert_eq!(
+            ids_ordered_by(&executor, slices(SortOrder::Asc, None)),
+            ids(vec![3, 1, 2])
+        );
+        assert_eq!(
+            ids_ordered_by(&executor, slices(SortOrder::Desc, None)),
+            ids(vec![2, 1, 3])
+        );
+        assert_eq!(
+            ids_ordered_by(&executor, slices(SortOrder::Asc, Some(NullsOrder::First))),
+            ids(vec![2, 3, 1])
+        );
+    }
+
+    #[test]
+    fn exists_filters_rows_by_whether_a_correlated_subquery_has_rows() {
+        let mut executor = farms_executor(ForeignKeyAction::Restrict);
+        for (id, acres) in vec![(1, 40), (2, 10), (3, 25)] {
+            insert_into(
+                &mut executor,
+                "farms",
+                vec![Value::Integer(id), Value::Integer(acres)],
+            )
+            .unwrap();
+        }
+        for (id, farm) in vec![(1, 3), (2, 1), (3, 3)] {
+            insert_into(
+                &mut executor,
+                "trees",
+                vec![Value::Integer(id), Value::Integer(farm)],
+            )
+            .unwrap();
+        }
+        let farm_ids_where = |predicate| {
+            executor
+                .select(ast::Selection::new(
+                    "farms",
+                    ColumnSet::Names(vec!["id".to_string()]),
+                    Some(predicate),
+                ))
+                .map(|rows| rows.map(|row| row[0].clone()).collect::<Vec<Value>>())
+        };
+        let trees_where = |predicate| {
+            Box::new(ast::Selection::new(
+                "trees",
+                ColumnSet::WildCard,
+                Some(predicate),
+            ))
+        };
+        let has_trees = || {
+            Predicate::Exists(trees_where(Predicate::ColumnComparison(
+                "trees.farm".to_string(),
+                Operator::Eq,
+                "farms.id".to_string(),
+            )))
+        };
+
+        assert_eq!(
+            farm_ids_where(has_trees()),
+            Ok(vec![Value::Integer(1), Value::Integer(3)])
+        );
+        assert_eq!(
+            farm_ids_where(Predicate::Not(Box::new(has_trees()))),
+            Ok(vec![Value::Integer(2)])
+        );
+        assert_eq!(
+            farm_ids_where(Predicate::And(
+                Box::new(Predicate::Comparison(
+                    "farms.acres".to_string(),
+                    Operator::Lt,
+                    Value::Integer(30)
+                )),
+                Box::new(Predicate::Exists(trees_where(Predicate::ColumnComparison(
+                    "id".to_string(),
+                    Operator::Lt,
+                    "farms.acres".to_string(),
+                )))),
+            )),
+            Ok(vec![Value::Integer(2), Value::Integer(3)])
+        );
+        assert_eq!(
+            farm_ids_where(Predicate::Exists(trees_where(Predicate::Comparison(
+                "id".to_string(),
+                Operator::Gt,
+                Value::Integer(3)
+            )))),
+            Ok(vec![])
+        );
+        assert_eq!(
+            farm_ids_where(Predicate::Exists(trees_where(Predicate::IsNotNull(
+                "farm".to_string()
+            )))),
+            Ok(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::Integer(3)
+            ])
+        );
+    }
+
+    #[test]
+    fn read_only_executors_reject_every_write() {
+        let mut executor = new_executor_with_table(apples_schema());
+        insert_apples(&mut executor, vec![(1, Value::Integer(4))]);
+        executor.set_read_only(true);
+        let read_only = Err("attempt to write a readonly database".to_string());
+
+        assert_eq!(
+            executor
+                .select(ast::Selection::new("apples", ColumnSet::WildCard, None))
+                .map(|rows| rows.collect::<Vec<Vec<Value>>>()),
+            Ok(vec![vec![Value::Integer(1), Value::Integer(4)]])
+        );
+        assert_eq!(executor.table_names(), vec!["apples".to_string()]);
+        assert_eq!(executor.schema("apples"), Some(apples_schema()));
+        assert_eq!(executor.row_count("apples"), Ok(1));
+
+        let rows = RowTree::new(4, 16, bptree::Serializer::Mock);
+        assert_eq!(
+            executor.add_table(
+                table::Table::new(
+                    TableSchema::new("pears", vec![ast::Column::new("id", true)]),
+                    rows
+                )
+                .unwrap()
+            ),
+            read_only
+        );
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "apples",
+                vec![Value::Integer(2), Value::Null]
+            )
+            .map(|_| ()),
+            read_only
+        );
+        assert_eq!(executor.truncate("apples"), read_only);
+        assert_eq!(
+            executor.alter(Alteration::new(
+                "apples",
+                ast::Column::new("seeds", false),
+                None
+            )),
+            read_only
+        );
+        assert_eq!(executor.vacuum(), read_only);
+        assert_eq!(
+            executor
+                .import_table("apples", r#"[{"id": 2}]"#)
+                .map(|_| ()),
+            read_only
+        );
+        assert_eq!(
+            executor
+                .import_csv("apples", "2,3".as_bytes(), false)
+                .map(|_| ()),
+            read_only
+        );
+        assert_eq!(executor.row_count("apples"), Ok(1));
+        assert_eq!(executor.table_names(), vec!["apples".to_string()]);
+
+        executor.set_read_only(false);
+        assert_eq!(executor.truncate("apples"), Ok(()));
+    }
+
+    #[test]
+    fn before_insert_hooks_can_reject_rows() {
+        let mut executor = new_executor_with_table(apples_schema());
+        executor
+            .add_before_insert_hook(
+                "apples",
+                Box::new(|row| match &row[1] {
+                    Value::Integer(slices) if *slices > 8 => {
+                        Err(format!("too many slices: {}", slices))
+                    }
+                    _ => Ok(()),
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "apples",
+                vec![Value::Integer(1), Value::Integer(8)]
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "apples",
+                vec![Value::Integer(2), Value::Integer(9)]
+            ),
+            Err("too many slices: 9".to_string())
+        );
+        assert_eq!(
+            executor.insert(ast::Insertion::new(
+                "apples",
+                Some(vec!["id".to_string()]),
+                vec![Value::Integer(3)],
+            )),
+            Ok(vec![])
+        );
+        assert_eq!(
+            executor.import_table("apples", r#"[{"id": 4, "slices": 12}]"#),
+            Err("too many slices: 12".to_string())
+        );
+        assert_eq!(executor.row_count("apples"), Ok(2));
+        assert_eq!(
+            executor.add_before_insert_hook("pears", Box::new(|_| Ok(()))),
+            Err("no such table: pears".to_string())
+        );
+    }
+
+    #[test]
+    fn named_columns_are_checked_before_hooks_run() {
+        let mut executor = new_executor_with_table(apples_schema());
+        executor
+            .add_before_insert_hook(
+                "apples",
+                Box::new(|row| match &row[1] {
+                    Value::Integer(slices) if *slices > 8 => {
+                        Err(format!("too many slices: {}", slices))
+                    }
+                    _ => Ok(()),
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(
+            executor.insert(ast::Insertion::new(
+                "apples",
+                Some(vec!["slices".to_string(), "slices".to_string()]),
+                vec![Value::Integer(3), Value::Integer(9)],
+            )),
+            Err("duplicate column name: slices".to_string())
+        );
+        assert_eq!(
+            executor.insert(ast::Insertion::new(
+                "apples",
+                Some(vec!["seeds".to_string(), "slices".to_string()]),
+                vec![Value::Integer(1), Value::Integer(9)],
+            )),
+            Err("no such column: seeds".to_string())
+        );
+        assert_eq!(executor.row_count("apples"), Ok(0));
+    }
+
+    #[test]
+    fn before_insert_hooks_run_in_the_order_they_were_added() {
+        let mut executor = new_executor_with_table(apples_schema());
+        let calls = Rc::new(RefCell::new(vec![]));
+        for name in vec!["first", "second"] {
+            let calls = calls.clone();
+            executor
+                .add_before_insert_hook(
+                    "apples",
+                    Box::new(move |row| {
+                        calls.borrow_mut().push(name);
+                        if name == "first" && row[1] == Value::Null {
+                            return Err("slices is required".to_string());
+                        }
+                        Ok(())
+                    }),
+                )
+                .unwrap();
+        }
+
+        insert_into(
+            &mut executor,
+            "apples",
+            vec![Value::Integer(1), Value::Integer(2)],
+        )
+        .unwrap();
+        assert_eq!(*calls.borrow(), vec!["first", "second"]);
+
+        calls.borrow_mut().clear();
+        assert_eq!(
+            insert_into(
+                &mut executor,
+                "apples",
+                vec![Value::Integer(2), Value::Null]
+            ),
+            Err("slices is required".to_string())
+        );
+        assert_eq!(*calls.borrow(), vec!["first"]);
+    }
+
+    #[test]
+    fn selections_are_described_without_running_them() {
+        let executor = new_executor_with_table(TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new_with_data_type("variety", DataType::Text, false, false),
+                ast::Column::new_with_data_type("ripe", DataType::Boolean, false, false),
+                ast::Column::new_with_data_type("photo", DataType::Any, false, false),
+            ],
+        ));
+        let describe = |columns| executor.describe(&ast::Selection::new("apples", columns, None));
+
+        assert_eq!(
+            describe(ColumnSet::WildCard),
+            Ok(vec![
+                ("id".to_string(), DataType::Integer),
+                ("variety".to_string(), DataType::Text),
+                ("ripe".to_string(), DataType::Boolean),
+                ("photo".to_string(), DataType::Any),
+            ])
+        );
+        assert_eq!(
+            describe(ColumnSet::Names(vec!["ripe".to_string(), "id".to_string()])),
+            Ok(vec![
+                ("ripe".to_string(), DataType::Boolean),
+                ("id".to_string(), DataType::Integer),
+            ])
+        );
+        assert_eq!(
+            describe(ColumnSet::Count),
+            Ok(vec![("count(*)".to_string(), DataType::Integer)])
+        );
+        assert_eq!(
+            describe(ColumnSet::Names(vec!["seeds".to_string()])),
+            Err("no such column: seeds".to_string())
+        );
+        assert_eq!(
+            executor.describe(&ast::Selection::new("pears", ColumnSet::WildCard, None)),
+            Err("no such table: pears".to_string())
+        );
+    }
+
+    #[test]
+    fn rows_are_filtered_by_a_subquery() {
+        let mut executor = farms_executor(ForeignKeyAction::Restrict);
+        for (id, acres) in vec![(1, 40), (2, 10), (3, 25)] {
+            insert_into(
+                &mut executor,
+                "farms",
+                vec![Value::Integer(id), Value::Integer(acres)],
+            )
+            .unwrap();
+        }
+        for (id, farm) in vec![(1, 3), (2, 1), (3, 3)] {
+            insert_into(
+                &mut executor,
+                "trees",
+                vec![Value::Integer(id), Value::Integer(farm)],
+            )
+            .unwrap();
+        }
+        let trees = |columns| ast::Selection::new("trees", columns, None);
+        let farms_where = |predicate| {
+            executor
+                .select(ast::Selection::new(
+                    "farms",
+                    ColumnSet::Names(vec!["acres".to_string()]),
+                    Some(predicate),
+                ))
+                .map(|rows| rows.collect::<Vec<Vec<Value>>>())
+        };
+
+        assert_eq!(
+            farms_where(Predicate::InSubquery(
+                "id".to_string(),
+                Box::new(trees(ColumnSet::Names(vec!["farm".to_string()])))
+            )),
+            Ok(vec![vec![Value::Integer(40)], vec![Value::Integer(25)]])
+        );
+        assert_eq!(
+            farms_where(Predicate::ComparisonSubquery(
+                "id".to_string(),
+                Operator::Eq,
+                Box::new(trees(ColumnSet::Count))
+            )),
+            Ok(vec![vec![Value::Integer(25)]])
+        );
+        assert_eq!(
+            farms_where(Predicate::ComparisonSubquery(
+                "id".to_string(),
+                Operator::Eq,
+                Box::new(trees(ColumnSet::Names(vec!["farm".to_string()])))
+            )),
+            Err("scalar subquery returned more than one row".to_string())
+        );
+        assert_eq!(
+            farms_where(Predicate::InSubquery(
+                "id".to_string(),
+                Box::new(trees(ColumnSet::WildCard))
+            )),
+            Err("sub-select returns 2 columns - expected 1".to_string())
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_execute {
+    use super::*;
+    use crate::{bptree, table};
+    use pretty_assertions::assert_eq;
+
+    type RowTree = bptree::BPTree<table::Key, Vec<Value>>;
+
+    fn new_table(schema: TableSchema) -> Result<table::Table<RowTree>, String> {
+        table::Table::new(schema, RowTree::new(4, 16, bptree::Serializer::Mock))
+    }
+
+    fn execute(
+        executor: &mut Executor<table::Table<RowTree>>,
+        sql: &str,
+    ) -> Result<ExecOutcome, String> {
+        executor.execute(sql, new_table)
+    }
+
+    #[test]
+    fn statements_are_parsed_and_run() {
+        let mut executor = Executor::new();
+        assert_eq!(
+            execute(
+                &mut executor,
+                "CREATE TABLE apples(id INTEGER PRIMARY KEY, slices INTEGER);"
+            ),
+            Ok(ExecOutcome::Done)
+        );
+        assert_eq!(
+            execute(&mut executor, "INSERT INTO apples VALUES(1, 8);"),
+            Ok(ExecOutcome::RowsAffected(1))
+        );
+        assert_eq!(
+            execute(
+                &mut executor,
+                "INSERT INTO apples(slices) VALUES(6) RETURNING id;"
+            ),
+            Ok(ExecOutcome::Rows(vec![vec![Value::Integer(2)]]))
+        );
+        assert_eq!(
+            execute(&mut executor, "SELECT * FROM apples WHERE slices > 6;"),
+            Ok(ExecOutcome::Rows(vec![vec![
+                Value::Integer(1),
+                Value::Integer(8)
+            ]]))
+        );
+        assert_eq!(
+            execute(&mut executor, "TRUNCATE TABLE apples;"),
+            Ok(ExecOutcome::RowsAffected(2))
+        );
+    }
+
+    #[test]
+    fn only_one_statement_can_be_executed_at_a_time() {
+        let mut executor = Executor::new();
+        assert_eq!(
+            execute(
+                &mut executor,
+                "CREATE TABLE apples(id INTEGER PRIMARY KEY); SELECT * FROM apples;"
+            ),
+            Err("cannot execute more than one statement at a time, use execute_batch".to_string())
+        );
+        assert_eq!(executor.table_names(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn check_constraints_cannot_hold_subqueries() {
+        let mut executor = Executor::new();
+        execute(&mut executor, "CREATE TABLE u(id INTEGER PRIMARY KEY);").unwrap();
+        for check in &[
+            "a IN (SELECT id FROM u)",
+            "a = (SELECT id FROM u)",
+            "EXISTS (SELECT * FROM u)",
+            "a > 0 AND NOT EXISTS (SELECT * FROM u)",
+        ] {
+            let sql = format!("CREATE TABLE t(a INTEGER CHECK({}));", check);
+            assert_eq!(
+                execute(&mut executor, &sql),
+                Err("subqueries prohibited in CHECK constraints".to_string()),
+                "{}",
+                check
+            );
+            let sql = format!("CREATE TABLE t(a INTEGER, CHECK({}));", check);
+            assert_eq!(
+                execute(&mut executor, &sql),
+                Err("subqueries prohibited in CHECK constraints".to_string()),
+                "{}",
+                check
+            );
+        }
+        assert_eq!(executor.table_names(), vec!["u".to_string()]);
+    }
+
+    #[test]
+    fn batches_run_every_statement_in_order() {
+        let mut executor = Executor::new();
+        assert_eq!(
+            executor.execute_batch(
+                "CREATE TABLE apples(id INTEGER PRIMARY KEY, name TEXT);
+                 INSERT INTO apples VALUES(1, 'a;b');
+                 SELECT name FROM apples;",
+                new_table
+            ),
+            Ok(vec![
+                ExecOutcome::Done,
+                ExecOutcome::RowsAffected(1),
+                ExecOutcome::Rows(vec![vec![Value::Text("a;b".to_string())]]),
+            ])
+        );
+
+        assert!(executor
+            .execute_batch(
+                "INSERT INTO apples VALUES(2, 'c'); INSERT INTO apples VALUES(",
+                new_table
+            )
+            .is_err());
+        assert_eq!(executor.row_count("apples"), Ok(1));
+
+        assert_eq!(
+            executor.execute_batch(
+                "INSERT INTO apples VALUES(2, 'c'); INSERT INTO apples VALUES(1, 'd'); \
+                 INSERT INTO apples VALUES(3, 'e');",
+                new_table
+            ),
+            Err("duplicate entry: 1".to_string())
+        );
+        assert_eq!(executor.row_count("apples"), Ok(2));
     }
 }
@@ -2,16 +2,21 @@
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::collections::HashMap;
 
 mod ast;
 mod bptree;
 mod executor;
 mod pager;
+mod parser;
+mod result_set;
+mod row;
 mod sqlite3;
+mod statement;
 mod table;
 
 use bptree::BPTree;
-use lalrpop_util::ParseError;
+use parser::ParseErrorKind;
 
 use ast::{Ast, Value};
 
@@ -27,39 +32,27 @@ fn main() {
         match readline {
             Ok(buffer) => {
                 rl.add_history_entry(buffer.as_str());
-                let parse_result = sqlite3::AstParser::new().parse(buffer.as_str());
+                let parse_result = parser::parse(buffer.as_str());
                 if parse_result.is_err() {
-                    match parse_result.unwrap_err() {
-                        ParseError::UnrecognizedToken { token, expected: _ } => {
-                            println!("Unexpected token \"{}\" at column {}.", token.1, token.0)
-                        }
-                        ParseError::UnrecognizedEOF {
-                            location,
-                            expected: _,
-                        } => {
-                            if location > 0 {
-                                println!("Unexpected EOF at column {}", location);
-                            }
-                        }
-                        ParseError::InvalidToken { location } => {
-                            println!("Invalid token at column {}", location);
-                        }
-                        ParseError::ExtraToken { token } => {
-                            println!("Extra token \"{}\" at column {}", token.1, token.0)
-                        }
-                        err => {
-                            println!("{:#?}", err);
-                        }
+                    let err = parse_result.unwrap_err();
+                    if err.kind != ParseErrorKind::UnexpectedEOF || !buffer.trim().is_empty() {
+                        println!("{}", err);
                     }
                     continue;
                 }
-                let ast = parse_result.ok().unwrap();
+                let ast = match parse_result.ok().unwrap().bind(&HashMap::new()) {
+                    Err(err) => {
+                        print_err(&err);
+                        continue;
+                    }
+                    Ok(ast) => ast,
+                };
                 match ast {
                     Ast::Exit => break 'main,
                     Ast::Create(schema) => {
                         let result = table::Table::new(
                             schema,
-                            BPTree::<Value, Vec<Value>>::new(
+                            BPTree::<table::Key, Vec<Value>>::new(
                                 bptree_degree,
                                 bptree_page_byte_size,
                                 bptree::Serializer::RMP,
@@ -77,26 +70,61 @@ fn main() {
                     }
                     Ast::Insert(insertion) => {
                         let result = executor.insert(insertion);
-                        if result.is_err() {
-                            print_err(&result.unwrap_err());
+                        match result {
+                            Err(err) => print_err(&err),
+                            Ok(rows) => print_rows(rows.into_iter()),
                         }
                     }
                     Ast::Select(selection) => {
-                        let result = executor.select(selection);
+                        let result = executor.query(selection);
                         match result {
                             Err(err) => print_err(&err),
-                            Ok(rows) => {
-                                for row in rows {
-                                    for val in &row[..row.len() - 1] {
-                                        print!("{}|", val);
-                                    }
-                                    for val in &row[(row.len() - 1)..] {
-                                        print!("{}\n", val);
-                                    }
-                                }
-                            }
+                            Ok(result_set) => print!("{}", result_set),
+                        }
+                    }
+                    Ast::Truncate(table_name) => {
+                        let result = executor.truncate(&table_name);
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
                         }
                     }
+                    Ast::Vacuum => {
+                        let result = executor.vacuum();
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
+                        }
+                    }
+                    Ast::Alter(alteration) => {
+                        let result = executor.alter(alteration);
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
+                        }
+                    }
+                    Ast::Savepoint(name) => {
+                        let result = executor.savepoint(&name);
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
+                        }
+                    }
+                    Ast::ReleaseSavepoint(name) => {
+                        let result = executor.release_savepoint(&name);
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
+                        }
+                    }
+                    Ast::RollbackTo(name) => {
+                        let result = executor.rollback_to(&name);
+                        if result.is_err() {
+                            print_err(&result.unwrap_err());
+                        }
+                    }
+                    Ast::Explain(ast) => match *ast {
+                        Ast::Select(selection) => match executor.explain(selection) {
+                            Err(err) => print_err(&err),
+                            Ok(plan) => println!("{}", plan),
+                        },
+                        ast => print_err(&format!("cannot explain {:?}", ast)),
+                    },
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -113,3 +141,14 @@ fn main() {
     }
     rl.save_history("history.txt").unwrap();
 }
+
+fn print_rows<I: Iterator<Item = Vec<Value>>>(rows: I) {
+    for row in rows {
+        for val in &row[..row.len() - 1] {
+            print!("{}|", val);
+        }
+        for val in &row[(row.len() - 1)..] {
+            print!("{}\n", val);
+        }
+    }
+}
@@ -1,6 +1,47 @@
-use crate::ast::Value;
-use rmp_serde;
-use std::io::{Read, Seek, Write};
+use crate::ast::{TableSchema, Value};
+use crate::table::{RowFormat, RowSerializer};
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+pub const DEFAULT_PAGE_SIZE: u16 = 4096;
+const MIN_PAGE_SIZE: u16 = 512;
+
+/// Same layout as SQLite's database header: a magic string followed by the
+/// big-endian page size, padded with zeros to `HEADER_SIZE` bytes.
+const HEADER_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+
+/// Every page starts with a big-endian CRC-32 of the rest of the page, then
+/// the big-endian length of the bytes stored after the header and the
+/// big-endian number of the next page of its chain. A row too big for one
+/// page continues on a chain of overflow pages, and free pages are chained
+/// together in the same way.
+const PAGE_HEADER_SIZE: usize = 10;
+/// Ends a chain of pages
+const NO_PAGE: u32 = u32::MAX;
+/// Where the header keeps the first free page and the number of free pages,
+/// the same offset as SQLite's free list
+const FREE_LIST_OFFSET: usize = 32;
+
+/// Reason the row of a page couldn't be read
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageError {
+    /// The page lies past the end of the database
+    NoSuchPage { page_number: u32 },
+    /// The page is cut short or doesn't match its checksum
+    CorruptPage { page_number: u32 },
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::NoSuchPage { page_number } => {
+                write!(f, "page {} does not exist", page_number)
+            }
+            PageError::CorruptPage { page_number } => write!(f, "page {} is corrupt", page_number),
+        }
+    }
+}
 
 pub struct Pager<F: Write + Read + Seek> {
     transaction_log: F,
@@ -8,27 +49,444 @@ pub struct Pager<F: Write + Read + Seek> {
     page_size: u16,
     page_cache: lru::LruCache<u32, Vec<Value>>,
     num_pages_on_disk: u32,
+    first_free_page: u32,
+    num_free_pages: u32,
+    serializer: Box<dyn RowSerializer>,
 }
 
 impl<F: Write + Read + Seek> Pager<F> {
+    /// Opens the database in `file`, writing a header for `page_size` if the
+    /// file is empty and otherwise checking that it was created with the
+    /// same page size.
     pub fn new(
         transaction_log: F,
-        file: F,
+        mut file: F,
         page_cache: lru::LruCache<u32, Vec<Value>>,
         page_size: u16,
-    ) -> Pager<F> {
-        return Pager {
+        row_format: RowFormat,
+    ) -> Result<Pager<F>, String> {
+        if page_size < MIN_PAGE_SIZE || !page_size.is_power_of_two() {
+            return Err(format!(
+                "page size must be a power of two of at least {} but got {}",
+                MIN_PAGE_SIZE, page_size
+            ));
+        }
+
+        let file_size = file.seek(SeekFrom::End(0)).map_err(|err| err.to_string())?;
+        let mut num_pages_on_disk = 0;
+        let mut first_free_page = NO_PAGE;
+        let mut num_free_pages = 0;
+        if file_size == 0 {
+            let mut header = vec![0; HEADER_SIZE];
+            header[..16].copy_from_slice(HEADER_MAGIC);
+            header[16..18].copy_from_slice(&page_size.to_be_bytes());
+            header[FREE_LIST_OFFSET..FREE_LIST_OFFSET + 4].copy_from_slice(&NO_PAGE.to_be_bytes());
+            file.write_all(&header).map_err(|err| err.to_string())?;
+        } else {
+            let mut header = [0; FREE_LIST_OFFSET + 8];
+            file.seek(SeekFrom::Start(0))
+                .and_then(|_| file.read_exact(&mut header))
+                .map_err(|_| "file is not a database".to_string())?;
+            if &header[..16] != HEADER_MAGIC {
+                return Err("file is not a database".to_string());
+            }
+            let file_page_size = u16::from_be_bytes([header[16], header[17]]);
+            if file_page_size != page_size {
+                return Err(format!(
+                    "database has a page size of {} but {} was requested",
+                    file_page_size, page_size
+                ));
+            }
+            first_free_page = read_u32(&header[FREE_LIST_OFFSET..]);
+            num_free_pages = read_u32(&header[FREE_LIST_OFFSET + 4..]);
+            // a page cut short still counts so that reading it reports it as
+            // corrupt
+            let pages_size = file_size.saturating_sub(HEADER_SIZE as u64);
+            num_pages_on_disk = ((pages_size + page_size as u64 - 1) / page_size as u64) as u32;
+        }
+
+        Ok(Pager {
             transaction_log,
             file,
             page_size,
             page_cache,
-            num_pages_on_disk: 0,
+            num_pages_on_disk,
+            first_free_page,
+            num_free_pages,
+            serializer: row_format.serializer(),
+        })
+    }
+
+    pub fn insert(&self, row: &[Value]) -> Result<u32, String> {
+        self.serializer.serialize(row)?;
+        Ok(0)
+    }
+
+    /// Number of an empty page to write a row to, reusing a freed page if
+    /// there is one and otherwise adding a page to the end of the database
+    pub fn allocate_page(&mut self) -> Result<u32, String> {
+        let page_number = if self.first_free_page == NO_PAGE {
+            self.num_pages_on_disk += 1;
+            self.num_pages_on_disk - 1
+        } else {
+            let page_number = self.first_free_page;
+            let (_, next) = self
+                .read_chained_page(page_number)
+                .map_err(|err| err.to_string())?;
+            self.first_free_page = next;
+            self.num_free_pages -= 1;
+            self.write_free_list()?;
+            page_number
         };
+
+        self.write_chained_page(page_number, &[], NO_PAGE)?;
+        Ok(page_number)
+    }
+
+    /// Number of pages that were freed and not allocated again since
+    pub fn num_free_pages(&self) -> u32 {
+        self.num_free_pages
+    }
+
+    /// Writes `row` as the only row of page `page_number`, which must not be
+    /// free, along with a checksum that is verified whenever the page is read
+    /// back. The part of the row that doesn't fit goes on overflow pages,
+    /// replacing any that the page had before.
+    pub fn write_page(&mut self, page_number: u32, row: &[Value]) -> Result<(), String> {
+        let serialized = self.serializer.serialize(row)?;
+        if page_number < self.num_pages_on_disk {
+            if let Ok((_, next)) = self.read_chained_page(page_number) {
+                self.free_chain(next)?;
+            }
+        }
+        self.num_pages_on_disk = self.num_pages_on_disk.max(page_number + 1);
+
+        let mut chunks = serialized
+            .chunks(self.page_size as usize - PAGE_HEADER_SIZE)
+            .collect::<Vec<&[u8]>>();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let mut page_numbers = vec![page_number];
+        for _ in 1..chunks.len() {
+            page_numbers.push(self.allocate_page()?);
+        }
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let next = page_numbers.get(i + 1).cloned().unwrap_or(NO_PAGE);
+            self.write_chained_page(page_numbers[i], chunk, next)?;
+        }
+
+        self.page_cache.put(page_number, row.to_vec());
+        Ok(())
+    }
+
+    /// Frees page `page_number` along with its overflow pages so that they
+    /// can be allocated again
+    pub fn delete_page(&mut self, page_number: u32) -> Result<(), String> {
+        if page_number >= self.num_pages_on_disk {
+            return Err(PageError::NoSuchPage { page_number }.to_string());
+        }
+
+        self.page_cache.pop(&page_number);
+        let (_, next) = self
+            .read_chained_page(page_number)
+            .map_err(|err| err.to_string())?;
+        self.free_chain(next)?;
+        self.free_page(page_number)
+    }
+
+    /// Reads the row of page `page_number`, failing rather than returning
+    /// garbage if the page or one of its overflow pages is cut short or
+    /// doesn't match its checksum
+    pub fn read_page(
+        &mut self,
+        page_number: u32,
+        schema: &TableSchema,
+    ) -> Result<Vec<Value>, PageError> {
+        if let Some(row) = self.page_cache.get(&page_number) {
+            return Ok(row.clone());
+        }
+        if page_number >= self.num_pages_on_disk {
+            return Err(PageError::NoSuchPage { page_number });
+        }
+
+        let (mut serialized, mut next) = self.read_chained_page(page_number)?;
+        let mut num_overflow_pages = 0;
+        while next != NO_PAGE {
+            // a chain can't be longer than the database or point past its end
+            num_overflow_pages += 1;
+            if next >= self.num_pages_on_disk || num_overflow_pages >= self.num_pages_on_disk {
+                return Err(PageError::CorruptPage { page_number });
+            }
+            let (bytes, after) = self.read_chained_page(next)?;
+            serialized.extend(bytes);
+            next = after;
+        }
+        let row = self
+            .serializer
+            .deserialize(&serialized, schema)
+            .map_err(|_| PageError::CorruptPage { page_number })?;
+
+        self.page_cache.put(page_number, row.clone());
+        Ok(row)
+    }
+
+    /// Bytes stored on page `page_number` and the next page of its chain
+    fn read_chained_page(&mut self, page_number: u32) -> Result<(Vec<u8>, u32), PageError> {
+        let corrupt = || PageError::CorruptPage { page_number };
+        let mut page = vec![0; self.page_size as usize];
+        let offset = self.page_offset(page_number);
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut page))
+            .map_err(|_| corrupt())?;
+        if read_u32(&page) != crc32(&page[4..]) {
+            return Err(corrupt());
+        }
+        let len = u16::from_be_bytes([page[4], page[5]]) as usize;
+        if PAGE_HEADER_SIZE + len > page.len() {
+            return Err(corrupt());
+        }
+
+        Ok((
+            page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + len].to_vec(),
+            read_u32(&page[6..]),
+        ))
+    }
+
+    fn write_chained_page(
+        &mut self,
+        page_number: u32,
+        bytes: &[u8],
+        next: u32,
+    ) -> Result<(), String> {
+        let mut page = vec![0; self.page_size as usize];
+        page[4..6].copy_from_slice(&(bytes.len() as u16).to_be_bytes());
+        page[6..PAGE_HEADER_SIZE].copy_from_slice(&next.to_be_bytes());
+        page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + bytes.len()].copy_from_slice(bytes);
+        let checksum = crc32(&page[4..]);
+        page[..4].copy_from_slice(&checksum.to_be_bytes());
+
+        let offset = self.page_offset(page_number);
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(&page))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Frees every page of the chain starting at `page_number`
+    fn free_chain(&mut self, mut page_number: u32) -> Result<(), String> {
+        while page_number != NO_PAGE {
+            let (_, next) = self
+                .read_chained_page(page_number)
+                .map_err(|err| err.to_string())?;
+            self.free_page(page_number)?;
+            page_number = next;
+        }
+        Ok(())
+    }
+
+    fn free_page(&mut self, page_number: u32) -> Result<(), String> {
+        self.write_chained_page(page_number, &[], self.first_free_page)?;
+        self.first_free_page = page_number;
+        self.num_free_pages += 1;
+        self.write_free_list()
+    }
+
+    fn write_free_list(&mut self) -> Result<(), String> {
+        let mut free_list = [0; 8];
+        free_list[..4].copy_from_slice(&self.first_free_page.to_be_bytes());
+        free_list[4..].copy_from_slice(&self.num_free_pages.to_be_bytes());
+        self.file
+            .seek(SeekFrom::Start(FREE_LIST_OFFSET as u64))
+            .and_then(|_| self.file.write_all(&free_list))
+            .map_err(|err| err.to_string())
+    }
+
+    fn page_offset(&self, page_number: u32) -> u64 {
+        HEADER_SIZE as u64 + page_number as u64 * self.page_size as u64
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// CRC-32 as used by zlib and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl Pager<Cursor<Vec<u8>>> {
+    /// Pager whose file and transaction log live in memory, like SQLite's
+    /// `:memory:` databases. Nothing is ever written to disk.
+    pub fn in_memory(
+        page_cache: lru::LruCache<u32, Vec<Value>>,
+        page_size: u16,
+        row_format: RowFormat,
+    ) -> Result<Pager<Cursor<Vec<u8>>>, String> {
+        Pager::new(
+            Cursor::new(vec![]),
+            Cursor::new(vec![]),
+            page_cache,
+            page_size,
+            row_format,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+    use pretty_assertions::assert_eq;
+
+    fn open(file: Vec<u8>, page_size: u16) -> Result<Pager<Cursor<Vec<u8>>>, String> {
+        Pager::new(
+            Cursor::new(vec![]),
+            Cursor::new(file),
+            lru::LruCache::new(4),
+            page_size,
+            RowFormat::Binary,
+        )
+    }
+
+    #[test]
+    fn rows_bigger_than_a_page_continue_on_overflow_pages() {
+        let schema = TableSchema::new(
+            "notes",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new_with_data_type("body", ast::DataType::Text, false, false),
+            ],
+        );
+        let long_row = vec![Value::Integer(1), Value::Text("apple ".repeat(500))];
+        let short_row = vec![Value::Integer(2), Value::Null];
+        assert_eq!(
+            open(vec![], DEFAULT_PAGE_SIZE).unwrap().insert(&long_row),
+            Ok(0)
+        );
+
+        let mut pager = open(vec![], MIN_PAGE_SIZE).unwrap();
+        pager.write_page(0, &long_row).unwrap();
+        let num_overflow_pages = pager.num_pages_on_disk - 1;
+        assert!(num_overflow_pages >= 5);
+        let short_page = pager.allocate_page().unwrap();
+        assert_eq!(short_page, num_overflow_pages + 1);
+        pager.write_page(short_page, &short_row).unwrap();
+        let file = pager.file.into_inner();
+
+        let mut pager = open(file.clone(), MIN_PAGE_SIZE).unwrap();
+        assert_eq!(pager.read_page(0, &schema), Ok(long_row.clone()));
+        assert_eq!(pager.read_page(short_page, &schema), Ok(short_row.clone()));
+        pager.delete_page(0).unwrap();
+        assert_eq!(pager.num_free_pages(), num_overflow_pages + 1);
+
+        let mut pager = open(pager.file.into_inner(), MIN_PAGE_SIZE).unwrap();
+        assert_eq!(pager.num_free_pages(), num_overflow_pages + 1);
+        let page_number = pager.allocate_page().unwrap();
+        pager.write_page(page_number, &long_row).unwrap();
+        assert_eq!(pager.num_free_pages(), 0);
+        let reused = pager.file.into_inner();
+        assert_eq!(reused.len(), file.len());
+        let mut pager = open(reused, MIN_PAGE_SIZE).unwrap();
+        assert_eq!(pager.read_page(page_number, &schema), Ok(long_row));
+        assert_eq!(pager.read_page(short_page, &schema), Ok(short_row));
+
+        let mut flipped = file;
+        flipped[HEADER_SIZE + 2 * MIN_PAGE_SIZE as usize + PAGE_HEADER_SIZE] ^= 0xFF;
+        let mut pager = open(flipped, MIN_PAGE_SIZE).unwrap();
+        assert_eq!(
+            pager.read_page(0, &schema),
+            Err(PageError::CorruptPage { page_number: 2 })
+        );
+    }
+
+    #[test]
+    fn page_size_must_match_the_database() {
+        let pager = open(vec![], 8192).unwrap();
+        let file = pager.file.into_inner();
+        assert_eq!(file.len(), HEADER_SIZE);
+
+        assert!(open(file.clone(), 8192).is_ok());
+        assert_eq!(
+            open(file, 4096).err(),
+            Some("database has a page size of 8192 but 4096 was requested".to_string())
+        );
+    }
+
+    #[test]
+    fn checksum_matches_the_zlib_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn corrupt_pages_are_reported_instead_of_read() {
+        let schema = TableSchema::new(
+            "apples",
+            vec![
+                ast::Column::new("id", true),
+                ast::Column::new("slices", false),
+            ],
+        );
+        let mut pager = open(vec![], MIN_PAGE_SIZE).unwrap();
+        pager
+            .write_page(0, &[Value::Integer(1), Value::Integer(8)])
+            .unwrap();
+        pager
+            .write_page(1, &[Value::Integer(2), Value::Null])
+            .unwrap();
+        let file = pager.file.into_inner();
+        assert_eq!(file.len(), HEADER_SIZE + 2 * MIN_PAGE_SIZE as usize);
+
+        let mut pager = open(file.clone(), MIN_PAGE_SIZE).unwrap();
+        assert_eq!(
+            pager.read_page(1, &schema),
+            Ok(vec![Value::Integer(2), Value::Null])
+        );
+        assert_eq!(
+            pager.read_page(2, &schema),
+            Err(PageError::NoSuchPage { page_number: 2 })
+        );
+
+        let mut flipped = file.clone();
+        flipped[HEADER_SIZE + MIN_PAGE_SIZE as usize + PAGE_HEADER_SIZE] ^= 0xFF;
+        let mut pager = open(flipped, MIN_PAGE_SIZE).unwrap();
+        assert_eq!(
+            pager.read_page(0, &schema),
+            Ok(vec![Value::Integer(1), Value::Integer(8)])
+        );
+        assert_eq!(
+            pager.read_page(1, &schema),
+            Err(PageError::CorruptPage { page_number: 1 })
+        );
+
+        let mut truncated = file;
+        truncated.truncate(HEADER_SIZE + MIN_PAGE_SIZE as usize + 10);
+        let mut pager = open(truncated, MIN_PAGE_SIZE).unwrap();
+        assert_eq!(
+            pager.read_page(1, &schema),
+            Err(PageError::CorruptPage { page_number: 1 })
+        );
+        assert_eq!(
+            PageError::CorruptPage { page_number: 1 }.to_string(),
+            "page 1 is corrupt"
+        );
     }
 
-    pub fn insert(&self, row: &[Value]) -> u32 {
-        let serialized = rmp_serde::to_vec(&row).unwrap();
-        println!("{:#?}", serialized);
-        0
+    #[test]
+    fn page_size_must_be_a_power_of_two() {
+        assert!(open(vec![], 256).is_err());
+        assert!(open(vec![], 5000).is_err());
+        assert!(open(vec![], 32768).is_ok());
     }
 }
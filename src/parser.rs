@@ -0,0 +1,150 @@
+use crate::ast::Ast;
+use crate::sqlite3;
+use lalrpop_util;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidToken,
+    UnexpectedToken(String),
+    UnexpectedEOF,
+    ExtraToken(String),
+    Other(String),
+}
+
+/// A parse failure located by its 1-based line and column in the statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    /// Terminals that would have been accepted at the error location
+    pub expected: Vec<String>,
+    /// The offending line with a `^` under the error location
+    pub snippet: String,
+}
+
+impl ParseError {
+    fn new(
+        statement: &str,
+        kind: ParseErrorKind,
+        location: usize,
+        expected: Vec<String>,
+    ) -> ParseError {
+        let before = &statement[..location];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let column = before[line_start..].chars().count() + 1;
+        let line_text = statement[line_start..].lines().next().unwrap_or("");
+
+        ParseError {
+            kind,
+            line,
+            column,
+            expected: expected
+                .iter()
+                .map(|terminal| terminal.trim_matches('"').to_string())
+                .collect(),
+            snippet: format!("{}\n{}^", line_text, " ".repeat(column - 1)),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::InvalidToken => write!(f, "Invalid token")?,
+            ParseErrorKind::UnexpectedToken(token) => write!(f, "Unexpected token \"{}\"", token)?,
+            ParseErrorKind::UnexpectedEOF => write!(f, "Unexpected EOF")?,
+            ParseErrorKind::ExtraToken(token) => write!(f, "Extra token \"{}\"", token)?,
+            ParseErrorKind::Other(message) => write!(f, "{}", message)?,
+        }
+        write!(f, " at line {}, column {}", self.line, self.column)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected one of: {}", self.expected.join(", "))?;
+        }
+        write!(f, "\n{}", self.snippet)
+    }
+}
+
+pub fn parse(statement: &str) -> Result<Ast, ParseError> {
+    sqlite3::AstParser::new()
+        .parse(statement)
+        .map_err(|err| match err {
+            lalrpop_util::ParseError::InvalidToken { location } => {
+                ParseError::new(statement, ParseErrorKind::InvalidToken, location, vec![])
+            }
+            lalrpop_util::ParseError::UnrecognizedEOF { location, expected } => {
+                ParseError::new(statement, ParseErrorKind::UnexpectedEOF, location, expected)
+            }
+            lalrpop_util::ParseError::UnrecognizedToken {
+                token: (location, token, _),
+                expected,
+            } => ParseError::new(
+                statement,
+                ParseErrorKind::UnexpectedToken(token.to_string()),
+                location,
+                expected,
+            ),
+            lalrpop_util::ParseError::ExtraToken {
+                token: (location, token, _),
+            } => ParseError::new(
+                statement,
+                ParseErrorKind::ExtraToken(token.to_string()),
+                location,
+                vec![],
+            ),
+            lalrpop_util::ParseError::User { error } => ParseError::new(
+                statement,
+                ParseErrorKind::Other(error.to_string()),
+                0,
+                vec![],
+            ),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn missing_paren_is_located() {
+        let err = parse("CREATE TABLE apples(slices INTEGER;").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedToken(";".to_string()));
+        assert_eq!((err.line, err.column), (1, 35));
+        assert!(err.expected.contains(&")".to_string()));
+        assert_eq!(
+            err.snippet,
+            "CREATE TABLE apples(slices INTEGER;\n                                  ^"
+        );
+    }
+
+    #[test]
+    fn unknown_keyword_is_located() {
+        let err = parse("SELEC * FROM apples;").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::UnexpectedToken("SELEC".to_string())
+        );
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(err.expected.contains(&"Select".to_string()));
+        assert!(err.expected.contains(&"Insert".to_string()));
+    }
+
+    #[test]
+    fn truncated_input_reports_position_past_the_last_token() {
+        let err = parse("SELECT * FROM").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedEOF);
+        assert_eq!((err.line, err.column), (1, 14));
+        assert_eq!(err.snippet, "SELECT * FROM\n             ^");
+    }
+
+    #[test]
+    fn errors_on_later_lines_are_located() {
+        let err = parse("SELECT *\nFROM apples\nWHERE id = ;").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedToken(";".to_string()));
+        assert_eq!((err.line, err.column), (3, 12));
+        assert_eq!(err.snippet, "WHERE id = ;\n           ^");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::ast::Value;
+use std::fmt;
+
+/// Selected rows along with the names of their columns, rendered the same
+/// way wherever they are printed
+///
+/// `Display` draws an ASCII table with every column as wide as its widest
+/// value, and `to_csv` writes the same rows as CSV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl ResultSet {
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<Value>>) -> ResultSet {
+        ResultSet { columns, rows }
+    }
+
+    /// Header line followed by a line per row. NULL is an empty field, as
+    /// `Executor::import_csv` reads it, and fields with a comma, quote or
+    /// line break in them are quoted.
+    pub fn to_csv(&self) -> String {
+        let mut csv = self
+            .columns
+            .iter()
+            .map(|column| csv_field(column))
+            .collect::<Vec<String>>()
+            .join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            let fields = row
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    value => csv_field(&value.to_string()),
+                })
+                .collect::<Vec<String>>();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Width of each column in terminal cells
+    fn widths(&self) -> Vec<usize> {
+        let mut widths = self
+            .columns
+            .iter()
+            .map(|column| display_width(column))
+            .collect::<Vec<usize>>();
+        for row in &self.rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(display_width(&cell(value)));
+            }
+        }
+        widths
+    }
+}
+
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths = self.widths();
+        let border = widths
+            .iter()
+            .map(|width| "-".repeat(width + 2))
+            .collect::<Vec<String>>()
+            .join("+");
+        let write_line = |f: &mut fmt::Formatter<'_>, cells: Vec<String>| {
+            write!(f, "|")?;
+            for (cell, width) in cells.iter().zip(&widths) {
+                let padding = " ".repeat(width - display_width(cell));
+                write!(f, " {}{} |", cell, padding)?;
+            }
+            writeln!(f)
+        };
+
+        writeln!(f, "+{}+", border)?;
+        write_line(f, self.columns.clone())?;
+        writeln!(f, "+{}+", border)?;
+        for row in &self.rows {
+            write_line(f, row.iter().map(cell).collect())?;
+        }
+        writeln!(f, "+{}+", border)
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Number of terminal cells `text` takes up, where East Asian wide
+/// characters and emoji take two and combining marks take none
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+            0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn apples() -> ResultSet {
+        ResultSet::new(
+            vec!["id".to_string(), "variety".to_string()],
+            vec![
+                vec![Value::Integer(1), Value::Text("Fuji".to_string())],
+                vec![Value::Integer(20), Value::Null],
+                vec![Value::Integer(3), Value::Text("Honeycrisp".to_string())],
+            ],
+        )
+    }
+
+    #[test]
+    fn result_sets_are_drawn_as_aligned_tables() {
+        assert_eq!(
+            apples().to_string(),
+            "+----+------------+\n\
+             | id | variety    |\n\
+             +----+------------+\n\
+             | 1  | Fuji       |\n\
+             | 20 | NULL       |\n\
+             | 3  | Honeycrisp |\n\
+             +----+------------+\n"
+        );
+        assert_eq!(
+            ResultSet::new(vec!["id".to_string()], vec![]).to_string(),
+            "+----+\n| id |\n+----+\n+----+\n"
+        );
+    }
+
+    #[test]
+    fn wide_characters_take_up_two_cells() {
+        let result_set = ResultSet::new(
+            vec!["name".to_string()],
+            vec![
+                vec![Value::Text("りんご".to_string())],
+                vec![Value::Text("café".to_string())],
+            ],
+        );
+        assert_eq!(
+            result_set.to_string(),
+            "+--------+\n\
+             | name   |\n\
+             +--------+\n\
+             | りんご |\n\
+             | café   |\n\
+             +--------+\n"
+        );
+    }
+
+    #[test]
+    fn result_sets_are_written_as_csv() {
+        assert_eq!(apples().to_csv(), "id,variety\n1,Fuji\n20,\n3,Honeycrisp\n");
+
+        let result_set = ResultSet::new(
+            vec!["name".to_string()],
+            vec![vec![Value::Text("Pink \"Lady\", late".to_string())]],
+        );
+        assert_eq!(result_set.to_csv(), "name\n\"Pink \"\"Lady\"\", late\"\n");
+    }
+}
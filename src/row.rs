@@ -0,0 +1,73 @@
+use crate::ast::{TableSchema, Value};
+
+/// A type that can be decoded from a selected row
+///
+/// `schema` describes the selected columns in the order they appear in `row`.
+pub trait FromRow: Sized {
+    fn from_row(row: &[Value], schema: &TableSchema) -> Result<Self, String>;
+}
+
+/// Value of the column named `column_name`
+pub fn get<'a>(
+    row: &'a [Value],
+    schema: &TableSchema,
+    column_name: &str,
+) -> Result<&'a Value, String> {
+    schema
+        .columns
+        .iter()
+        .position(|column| column.name == column_name)
+        .and_then(|index| row.get(index))
+        .ok_or_else(|| format!("no such column: {}", column_name))
+}
+
+/// Integer in the column named `column_name`, which must not be NULL
+pub fn get_integer(row: &[Value], schema: &TableSchema, column_name: &str) -> Result<i64, String> {
+    get_optional_integer(row, schema, column_name)?
+        .ok_or_else(|| format!("column {} is NULL", column_name))
+}
+
+/// Integer in the column named `column_name`, or `None` if it is NULL
+pub fn get_optional_integer(
+    row: &[Value],
+    schema: &TableSchema,
+    column_name: &str,
+) -> Result<Option<i64>, String> {
+    match get(row, schema, column_name)? {
+        Value::Integer(i) => Ok(Some(*i)),
+        Value::Null => Ok(None),
+        value => Err(format!(
+            "column {} is not an integer: {}",
+            column_name, value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Column;
+    use pretty_assertions::assert_eq;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("slices", false)],
+        )
+    }
+
+    #[test]
+    fn integers_are_extracted_by_column_name() {
+        let row = vec![Value::Integer(1), Value::Null];
+        assert_eq!(get_integer(&row, &schema(), "id"), Ok(1));
+        assert_eq!(get_optional_integer(&row, &schema(), "slices"), Ok(None));
+        assert_eq!(
+            get_integer(&row, &schema(), "slices"),
+            Err("column slices is NULL".to_string())
+        );
+        assert_eq!(
+            get_integer(&row, &schema(), "seeds"),
+            Err("no such column: seeds".to_string())
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::ast::{self, Ast, DataType, Value};
+use crate::executor::{Executor, Insertion, Table};
+use crate::parser;
+use std::collections::HashMap;
+
+/// A parsed statement that can be executed repeatedly with different values
+/// bound to its `?n` parameters.
+pub struct PreparedStatement {
+    ast: Ast,
+    parameters: HashMap<usize, Value>,
+}
+
+impl PreparedStatement {
+    pub fn new(ast: Ast) -> PreparedStatement {
+        PreparedStatement {
+            ast,
+            parameters: HashMap::new(),
+        }
+    }
+
+    pub fn prepare(statement: &str) -> Result<PreparedStatement, String> {
+        parser::parse(statement)
+            .map(PreparedStatement::new)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn bind(&mut self, index: usize, value: Value) -> &mut PreparedStatement {
+        self.parameters.insert(index, value);
+        self
+    }
+
+    pub fn clear_bindings(&mut self) -> &mut PreparedStatement {
+        self.parameters.clear();
+        self
+    }
+
+    /// Name and declared type of every column the statement returns,
+    /// which is none for a statement that doesn't return rows
+    pub fn describe<T: Table>(
+        &self,
+        executor: &Executor<T>,
+    ) -> Result<Vec<(String, DataType)>, String> {
+        match &self.ast {
+            Ast::Select(selection) => executor.describe(selection),
+            Ast::Insert(insertion) => match Insertion::returning(insertion) {
+                None => Ok(vec![]),
+                Some(columns) => executor.describe(&ast::Selection::new(
+                    Insertion::table_name(insertion),
+                    columns,
+                    None,
+                )),
+            },
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Runs the statement with the currently bound parameters and returns
+    /// the selected rows, or the inserted row for an insert with RETURNING.
+    pub fn execute<T: Table>(&self, executor: &mut Executor<T>) -> Result<Vec<Vec<Value>>, String> {
+        match self.ast.bind(&self.parameters)? {
+            Ast::Insert(insertion) => executor.insert(insertion),
+            Ast::Select(selection) => executor.select(selection).map(|rows| rows.collect()),
+            ast => Err(format!("cannot execute prepared statement: {:?}", ast)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Column, TableSchema};
+    use crate::{bptree, table};
+    use pretty_assertions::assert_eq;
+
+    type RowTree = bptree::BPTree<table::Key, Vec<Value>>;
+
+    fn new_executor() -> Executor<table::Table<RowTree>> {
+        let schema = TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("slices", false)],
+        );
+        let rows = RowTree::new(4, 16, bptree::Serializer::Mock);
+        let mut executor = Executor::new();
+        executor
+            .add_table(table::Table::new(schema, rows).unwrap())
+            .unwrap();
+
+        executor
+    }
+
+    #[test]
+    fn statement_is_reused_with_different_bindings() {
+        let mut executor = new_executor();
+        let mut insert =
+            PreparedStatement::prepare("INSERT INTO apples(id, slices) VALUES(?1, ?2);").unwrap();
+        let mut select =
+            PreparedStatement::prepare("SELECT slices FROM apples WHERE id = ?1;").unwrap();
+
+        for id in 1..=3 {
+            insert
+                .bind(1, Value::Integer(id))
+                .bind(2, Value::Integer(id * 10));
+            insert.execute(&mut executor).unwrap();
+
+            select.bind(1, Value::Integer(id));
+            assert_eq!(
+                select.execute(&mut executor),
+                Ok(vec![vec![Value::Integer(id * 10)]])
+            );
+        }
+    }
+
+    #[test]
+    fn statement_is_described_before_it_is_executed() {
+        let executor = new_executor();
+        let describe = |statement| PreparedStatement::prepare(statement)?.describe(&executor);
+
+        assert_eq!(
+            describe("SELECT slices, id FROM apples WHERE id = ?1;"),
+            Ok(vec![
+                ("slices".to_string(), DataType::Integer),
+                ("id".to_string(), DataType::Integer)
+            ])
+        );
+        assert_eq!(
+            describe("INSERT INTO apples(id) VALUES(?1) RETURNING *;"),
+            Ok(vec![
+                ("id".to_string(), DataType::Integer),
+                ("slices".to_string(), DataType::Integer)
+            ])
+        );
+        assert_eq!(describe("INSERT INTO apples(id) VALUES(?1);"), Ok(vec![]));
+    }
+
+    #[test]
+    fn executing_with_an_unbound_parameter_fails() {
+        let mut executor = new_executor();
+        let mut insert =
+            PreparedStatement::prepare("INSERT INTO apples(id, slices) VALUES(?1, ?2);").unwrap();
+        insert.bind(1, Value::Integer(1));
+
+        assert_eq!(
+            insert.execute(&mut executor),
+            Err("unbound parameter ?2".to_string())
+        );
+    }
+
+    #[test]
+    fn parameters_must_be_numbered_from_one_within_range() {
+        for (statement, message) in &[
+            (
+                "SELECT slices FROM apples WHERE id = ?0;",
+                "parameters are numbered from ?1",
+            ),
+            (
+                "SELECT slices FROM apples WHERE id = ?99999999999999999999999;",
+                "parameter number is too large",
+            ),
+        ] {
+            match PreparedStatement::prepare(statement) {
+                Err(err) => assert!(err.contains(message), "{}", err),
+                Ok(_) => panic!("{} should not parse", statement),
+            }
+        }
+    }
+}
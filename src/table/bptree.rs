@@ -1,8 +1,28 @@
-use super::BPTree;
+use super::{BPTree, Key};
 use crate::{ast::Value, bptree};
 
-impl BPTree for bptree::BPTree<Value, Vec<Value>> {
-    fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String> {
+impl BPTree for bptree::BPTree<Key, Vec<Value>> {
+    fn insert(&mut self, key: Key, value: Vec<Value>) -> Result<(), String> {
         self.insert(bptree::Entry::new(key, value))
     }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn compact(&mut self) {
+        self.compact()
+    }
+
+    fn load(&mut self, entries: Vec<(Key, Vec<Value>)>) -> Result<(), String> {
+        self.load(entries.into_iter())
+    }
+
+    fn get(&self, key: &Key) -> Option<Vec<Value>> {
+        self.get(key)
+    }
+
+    fn range(&self, low: &Key, high: &Key) -> Vec<Vec<Value>> {
+        self.range(low, high)
+    }
 }
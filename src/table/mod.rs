@@ -1,7 +1,8 @@
-use crate::ast::{Column, Value};
+use crate::ast::{self, Collation, Column, DataType, Value};
 use crate::executor;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::IntoIterator;
 use std::iter::Iterator;
 
@@ -12,6 +13,9 @@ extern crate mockers_derive;
 use mockers_derive::mocked;
 
 mod bptree;
+mod serializer;
+
+pub use serializer::{RowFormat, RowSerializer};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct IndexedColumn {
@@ -23,10 +27,44 @@ impl executor::Column for IndexedColumn {
     fn name(&self) -> &String {
         &self.column.name
     }
+
+    fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Values of the primary key columns of a row, compared column by column
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key(pub Vec<Value>);
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [value] => write!(f, "{}", value),
+            values => write!(
+                f,
+                "({})",
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
 }
 
 pub trait BPTree: IntoIterator<Item = Vec<Value>> + Clone {
-    fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String>;
+    fn insert(&mut self, key: Key, value: Vec<Value>) -> Result<(), String>;
+    fn clear(&mut self);
+    /// Rebuilds the tree without any wasted space in its nodes
+    fn compact(&mut self);
+    /// Replaces every row with `entries`, which are sorted by key, in a
+    /// single pass
+    fn load(&mut self, entries: Vec<(Key, Vec<Value>)>) -> Result<(), String>;
+    fn get(&self, key: &Key) -> Option<Vec<Value>>;
+    /// Rows with a key in `[low, high]`, in key order
+    fn range(&self, low: &Key, high: &Key) -> Vec<Vec<Value>>;
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -34,18 +72,37 @@ pub struct Table<T: BPTree> {
     pub name: String,
     rows: T,
     columns: HashMap<String, IndexedColumn>,
-    pk_idx: usize,
+    /// Indices of the columns that rows are keyed by, which are the primary
+    /// key columns or else the first column
+    pk_indices: Vec<usize>,
+    /// Index of the integer primary key column that doubles as the rowid
+    rowid_idx: Option<usize>,
+    last_rowid: i64,
+    num_rows: usize,
+    last_inserted_key: Option<Key>,
+    foreign_keys: Vec<ast::ForeignKey>,
+    checks: Vec<ast::Predicate>,
 }
 
 impl<T: BPTree + 'static> executor::Table for Table<T> {
     fn select_rows(&self) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
         self.select_rows()
     }
-    fn select_rows_with_named_columns(
+    fn get(&self, key: &Vec<Value>) -> Result<Option<Vec<Value>>, String> {
+        self.get(key)
+    }
+    fn select_rows_by_primary_keys(
+        &self,
+        keys: &Vec<Value>,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+        self.select_rows_by_primary_keys(keys)
+    }
+    fn select_rows_in_primary_key_range(
         &self,
-        column_names: &Vec<String>,
+        low: &Value,
+        high: &Value,
     ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
-        self.select_rows_with_named_columns(column_names)
+        self.select_rows_in_primary_key_range(low, high)
     }
     fn insert_row(&mut self, row: Vec<Value>) -> Result<&mut dyn executor::Table, String> {
         self.insert_row(row)
@@ -60,10 +117,40 @@ impl<T: BPTree + 'static> executor::Table for Table<T> {
             .map(|table| table as &mut dyn executor::Table)
     }
 
+    fn insert_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, String> {
+        self.insert_rows(rows)
+    }
+
+    fn truncate(&mut self) {
+        self.truncate()
+    }
+
+    fn add_column(&mut self, column: Column, default: Value) -> Result<(), String> {
+        self.add_column(column, default)
+    }
+
+    fn restore(&mut self, schema: ast::TableSchema, rows: Vec<Vec<Value>>) -> Result<(), String> {
+        self.restore(schema, rows)
+    }
+
+    fn vacuum(&mut self) {
+        self.rows.compact()
+    }
+
     fn row_len(&self) -> usize {
         self.row_len()
     }
 
+    fn row_count(&self) -> usize {
+        self.num_rows
+    }
+
+    fn last_inserted_row(&self) -> Option<Vec<Value>> {
+        self.last_inserted_key
+            .as_ref()
+            .and_then(|key| self.rows.get(key))
+    }
+
     fn name(&self) -> String {
         return self.name.clone();
     }
@@ -71,12 +158,22 @@ impl<T: BPTree + 'static> executor::Table for Table<T> {
     fn columns(&self) -> Vec<Box<dyn executor::Column>> {
         self.columns()
     }
+
+    fn schema(&self) -> ast::TableSchema {
+        self.schema()
+    }
+
+    fn primary_key(&self) -> Option<String> {
+        self.primary_key()
+    }
 }
 
 #[cfg_attr(test, mocked)]
 pub trait TableSchema {
     fn table_name(&self) -> String;
     fn columns(&self) -> Vec<Column>;
+    fn foreign_keys(&self) -> Vec<ast::ForeignKey>;
+    fn checks(&self) -> Vec<ast::Predicate>;
     fn validate(&self) -> Result<(), String>;
 }
 
@@ -87,13 +184,20 @@ impl<T: BPTree + 'static> Table<T> {
     {
         table_schema.validate()?;
         let columns = table_schema.columns();
+        if columns.is_empty() {
+            return Err("table must have at least one column".to_string());
+        }
         let table_name = table_schema.table_name();
-        let mut pk_idx = 0;
+        let mut pk_indices = vec![];
+        let mut rowid_idx = None;
 
         let mut mapped_columns = HashMap::new();
         for (i, column) in columns.into_iter().enumerate() {
             if column.is_primary_key {
-                pk_idx = i;
+                pk_indices.push(i);
+                if column.data_type == DataType::Integer {
+                    rowid_idx = Some(i);
+                }
             }
 
             mapped_columns.insert(
@@ -104,44 +208,79 @@ impl<T: BPTree + 'static> Table<T> {
                 },
             );
         }
+        if pk_indices.len() > 1 {
+            rowid_idx = None;
+        }
+        if pk_indices.is_empty() {
+            pk_indices.push(0);
+        }
         return Ok(Table {
             name: table_name.to_lowercase(),
             rows,
-            pk_idx,
+            pk_indices,
+            rowid_idx,
+            last_rowid: 0,
+            num_rows: 0,
+            last_inserted_key: None,
+            foreign_keys: table_schema.foreign_keys(),
+            checks: table_schema.checks(),
             columns: mapped_columns,
         });
     }
+    /// Every row in key order, which is the order of the leaves of the tree
     pub fn select_rows(&self) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
         return Ok(Box::new(self.rows.clone().into_iter()));
     }
-    pub fn select_rows_with_named_columns(
-        &self,
-        column_names: &Vec<String>,
-    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
-        for column_name in column_names {
-            if self.columns.get(column_name).is_none() {
-                return Err(format!("no such column: {}", column_name));
-            }
-        }
-        let mut indices = Vec::new();
-        let result = self.indices(&column_names, &mut indices);
-        if result.is_err() {
-            return Err(result.unwrap_err());
+    /// Row whose key columns hold `key`
+    pub fn get(&self, key: &[Value]) -> Result<Option<Vec<Value>>, String> {
+        if key.len() != self.pk_indices.len() {
+            return Err(format!(
+                "table {} is keyed by {} columns but {} values were supplied",
+                self.name,
+                self.pk_indices.len(),
+                key.len()
+            ));
         }
 
-        return Ok(Box::new(
-            self.rows
-                .clone()
-                .into_iter()
-                .map(move |row| {
-                    let mut filtered_row = vec![];
-                    for i in &indices {
-                        filtered_row.push(row[*i].clone())
-                    }
-                    filtered_row
-                })
-                .into_iter(),
-        ));
+        Ok(self.rows.get(&self.collated_key(key.to_vec())))
+    }
+    /// Rows whose primary key is one of `keys`, in key order
+    pub fn select_rows_by_primary_keys(
+        &self,
+        keys: &Vec<Value>,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+        let mut keys = keys
+            .iter()
+            .map(|key| self.collated_key(vec![key.clone()]))
+            .collect::<Vec<Key>>();
+        keys.sort();
+        keys.dedup();
+
+        let rows = keys
+            .into_iter()
+            .filter_map(|key| self.rows.get(&key))
+            .collect::<Vec<Vec<Value>>>();
+        Ok(Box::new(rows.into_iter()))
+    }
+    pub fn select_rows_in_primary_key_range(
+        &self,
+        low: &Value,
+        high: &Value,
+    ) -> Result<Box<dyn Iterator<Item = Vec<Value>>>, String> {
+        let low = self.collated_key(vec![low.clone()]);
+        let high = self.collated_key(vec![high.clone()]);
+        Ok(Box::new(self.rows.range(&low, &high).into_iter()))
+    }
+    /// Name of the declared primary key column, if there is one
+    pub fn primary_key(&self) -> Option<String> {
+        let pk_idx = match self.pk_indices.as_slice() {
+            [pk_idx] => *pk_idx,
+            _ => return None,
+        };
+        self.columns
+            .values()
+            .find(|column| column.index == pk_idx && column.column.is_primary_key)
+            .map(|column| column.column.name.clone())
     }
     pub fn columns(&self) -> Vec<Box<dyn executor::Column>> {
         let mut columns = vec![];
@@ -152,12 +291,42 @@ impl<T: BPTree + 'static> Table<T> {
 
         columns
     }
-    pub fn insert_row(&mut self, row: Vec<Value>) -> Result<&mut Table<T>, String> {
+    pub fn schema(&self) -> ast::TableSchema {
+        let mut columns = self.columns.values().collect::<Vec<&IndexedColumn>>();
+        columns.sort_by_key(|column| column.index);
+        let mut columns = columns
+            .into_iter()
+            .map(|column| column.column.clone())
+            .collect::<Vec<Column>>();
+        let mut primary_key = vec![];
+        if self.pk_indices.len() > 1 {
+            // a composite key is declared as a table-level constraint
+            primary_key = self
+                .pk_indices
+                .iter()
+                .map(|index| columns[*index].name.clone())
+                .collect();
+            for column in &mut columns {
+                column.is_primary_key = false;
+            }
+        }
+
+        let mut schema = ast::TableSchema::new_with_primary_key(&self.name, columns, primary_key);
+        schema.foreign_keys = self.foreign_keys.clone();
+        schema.checks = self.checks.clone();
+        schema
+    }
+    pub fn insert_row(&mut self, mut row: Vec<Value>) -> Result<&mut Table<T>, String> {
         if row.len() != self.row_len() {
             return Err(self.wrong_num_of_columns_error(row.len()));
         }
 
-        self.rows.insert(row[self.pk_idx].clone(), row)?;
+        let rowid = self.assign_rowid(&mut row);
+        let key = self.key(&row);
+        self.rows.insert(key.clone(), row)?;
+        self.advance_rowid(rowid);
+        self.num_rows += 1;
+        self.last_inserted_key = Some(key);
 
         Ok(self)
     }
@@ -183,11 +352,174 @@ impl<T: BPTree + 'static> Table<T> {
             row_vec[*index] = value.clone();
         }
 
-        self.rows.insert(row_vec[self.pk_idx].clone(), row_vec)?;
+        let rowid = self.assign_rowid(&mut row_vec);
+        let key = self.key(&row_vec);
+        self.rows.insert(key.clone(), row_vec)?;
+        self.advance_rowid(rowid);
+        self.num_rows += 1;
+        self.last_inserted_key = Some(key);
 
         Ok(self)
     }
 
+    /// Inserts every row or, if any of them can't be, none of them, and
+    /// returns the rows with their assigned rowids in the order they were
+    /// given. Rows going into an empty table are sorted and loaded into the
+    /// tree in one pass rather than inserted one at a time.
+    pub fn insert_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, String> {
+        let last_rowid = self.last_rowid;
+        let mut entries = match self.keyed_rows(rows) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.last_rowid = last_rowid;
+                return Err(err);
+            }
+        };
+        let last_inserted_key = match entries.last() {
+            None => return Ok(vec![]),
+            Some((key, _)) => key.clone(),
+        };
+
+        let num_rows = entries.len();
+        let inserted = entries.iter().map(|(_, row)| row.clone()).collect();
+        if self.num_rows == 0 {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            self.rows.load(entries)?;
+        } else {
+            for (key, row) in entries {
+                self.rows.insert(key, row)?;
+            }
+        }
+        self.num_rows += num_rows;
+        self.last_inserted_key = Some(last_inserted_key);
+
+        Ok(inserted)
+    }
+
+    /// Assigns rowids to rows and pairs each with its key, checking that no
+    /// two keys are the same and that none is already taken
+    fn keyed_rows(&mut self, rows: Vec<Vec<Value>>) -> Result<Vec<(Key, Vec<Value>)>, String> {
+        let mut keys = HashSet::new();
+        let mut entries = vec![];
+        for mut row in rows {
+            if row.len() != self.row_len() {
+                return Err(self.wrong_num_of_columns_error(row.len()));
+            }
+            let rowid = self.assign_rowid(&mut row);
+            let key = self.key(&row);
+            if self.rows.get(&key).is_some() || !keys.insert(key.clone()) {
+                return Err(format!("duplicate entry: {}", key));
+            }
+            self.advance_rowid(rowid);
+            entries.push((key, row));
+        }
+
+        Ok(entries)
+    }
+
+    /// Removes every row and restarts the rowid sequence
+    pub fn truncate(&mut self) {
+        self.rows.clear();
+        self.last_rowid = 0;
+        self.num_rows = 0;
+        self.last_inserted_key = None;
+    }
+
+    /// Replaces the columns and rows of the table, e.g. to roll it back to
+    /// an earlier state. Rowids already handed out are never handed out
+    /// again, even if the rows that had them are gone.
+    pub fn restore(
+        &mut self,
+        schema: ast::TableSchema,
+        rows: Vec<Vec<Value>>,
+    ) -> Result<(), String> {
+        let mut tree = self.rows.clone();
+        tree.clear();
+        let mut table = Table::new(schema, tree)?;
+        table.insert_rows(rows)?;
+        table.last_inserted_key = None;
+        table.last_rowid = table.last_rowid.max(self.last_rowid);
+        *self = table;
+
+        Ok(())
+    }
+
+    /// Appends `column` to the table and backfills existing rows with
+    /// `default`
+    pub fn add_column(&mut self, column: Column, default: Value) -> Result<(), String> {
+        if self.columns.contains_key(&column.name) {
+            return Err(format!("duplicate column name: {}", column.name));
+        }
+        if column.is_primary_key {
+            return Err("Cannot add a PRIMARY KEY column".to_string());
+        }
+
+        let rows = self.rows.clone();
+        self.rows.clear();
+        for mut row in rows {
+            row.push(default.clone());
+            self.rows.insert(self.key(&row), row)?;
+        }
+        self.columns.insert(
+            column.name.clone(),
+            IndexedColumn {
+                index: self.row_len(),
+                column,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn key(&self, row: &[Value]) -> Key {
+        self.collated_key(
+            self.pk_indices
+                .iter()
+                .map(|index| row[*index].clone())
+                .collect(),
+        )
+    }
+
+    /// Key with each value folded by the collation of its key column, so
+    /// that values the collation treats as equal share a key
+    fn collated_key(&self, values: Vec<Value>) -> Key {
+        Key(values
+            .iter()
+            .zip(&self.pk_indices)
+            .map(|(value, index)| self.collation(*index).key(value))
+            .collect())
+    }
+
+    fn collation(&self, index: usize) -> Collation {
+        self.columns
+            .values()
+            .find(|column| column.index == index)
+            .map_or(Collation::default(), |column| column.column.collation)
+    }
+
+    /// Fills in the rowid column with the next sequential rowid if it was
+    /// omitted and returns the rowid the row will be stored under.
+    fn assign_rowid(&self, row: &mut Vec<Value>) -> Option<i64> {
+        let rowid_idx = self.rowid_idx?;
+        match row[rowid_idx] {
+            Value::Integer(rowid) => Some(rowid),
+            Value::Null => {
+                let rowid = self.last_rowid + 1;
+                row[rowid_idx] = Value::Integer(rowid);
+                Some(rowid)
+            }
+            _ => None,
+        }
+    }
+
+    fn advance_rowid(&mut self, rowid: Option<i64>) {
+        if let Some(rowid) = rowid {
+            if rowid > self.last_rowid {
+                self.last_rowid = rowid;
+            }
+        }
+    }
+
     fn indices(&self, column_names: &Vec<String>, dst: &mut Vec<usize>) -> Result<(), String> {
         for column_name in column_names {
             if !self.columns.contains_key(column_name) {
@@ -231,7 +563,27 @@ mod tests {
     }
 
     impl BPTree for MockBpTree {
-        fn insert(&mut self, key: Value, value: Vec<Value>) -> Result<(), String> {
+        fn insert(&mut self, key: Key, value: Vec<Value>) -> Result<(), String> {
+            panic!("not implemented")
+        }
+
+        fn clear(&mut self) {
+            panic!("not implemented")
+        }
+
+        fn compact(&mut self) {
+            panic!("not implemented")
+        }
+
+        fn load(&mut self, entries: Vec<(Key, Vec<Value>)>) -> Result<(), String> {
+            panic!("not implemented")
+        }
+
+        fn get(&self, key: &Key) -> Option<Vec<Value>> {
+            panic!("not implemented")
+        }
+
+        fn range(&self, low: &Key, high: &Key) -> Vec<Vec<Value>> {
             panic!("not implemented")
         }
     }
@@ -250,6 +602,8 @@ mod tests {
         let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
 
         scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(table_schema_handle.foreign_keys().and_return(vec![]));
+        scenario.expect(table_schema_handle.checks().and_return(vec![]));
 
         scenario.expect(
             table_schema_handle
@@ -280,6 +634,8 @@ mod tests {
         let (table_schema, table_schema_handle) = scenario.create_mock_for::<dyn TableSchema>();
 
         scenario.expect(table_schema_handle.validate().and_return(Ok(())));
+        scenario.expect(table_schema_handle.foreign_keys().and_return(vec![]));
+        scenario.expect(table_schema_handle.checks().and_return(vec![]));
 
         scenario.expect(
             table_schema_handle
@@ -299,4 +655,251 @@ mod tests {
         let result = table.insert_row_with_named_columns(row);
         assert_eq!(result.is_err(), true);
     }
+
+    fn new_bptree() -> crate::bptree::BPTree<Key, Vec<Value>> {
+        crate::bptree::BPTree::new(4, 16, crate::bptree::Serializer::Mock)
+    }
+
+    fn insert_count(table: &mut Table<crate::bptree::BPTree<Key, Vec<Value>>>, count: i64) {
+        let mut row = HashMap::new();
+        row.insert("count".to_string(), Value::Integer(count));
+        table.insert_row_with_named_columns(row).unwrap();
+    }
+
+    #[test]
+    fn invalid_schemas_are_rejected_at_create() {
+        let duplicate_column = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("id", false)],
+        );
+        assert_eq!(
+            Table::new(duplicate_column, new_bptree()).err(),
+            Some("duplicate column name: id".to_string())
+        );
+
+        let two_primary_keys = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("slices", true)],
+        );
+        assert_eq!(
+            Table::new(two_primary_keys, new_bptree()).err(),
+            Some("table \"apples\" has more than one primary key".to_string())
+        );
+
+        let no_columns = ast::TableSchema::new("apples", vec![]);
+        assert_eq!(
+            Table::new(no_columns, new_bptree()).err(),
+            Some("table must have at least one column".to_string())
+        );
+    }
+
+    #[test]
+    fn omitted_primary_key_is_assigned_the_next_rowid() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![
+                Column::new_with_autoincrement("id", true, true),
+                Column::new("count", false),
+            ],
+        );
+        let mut table = Table::new(schema, new_bptree()).unwrap();
+        insert_count(&mut table, 10);
+        insert_count(&mut table, 20);
+        insert_count(&mut table, 30);
+
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(2), Value::Integer(20)],
+                vec![Value::Integer(3), Value::Integer(30)],
+            ]
+        );
+    }
+
+    #[test]
+    fn omitted_primary_key_is_only_assigned_a_rowid_if_it_is_an_integer() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![
+                Column::new_with_data_type("name", DataType::Text, true, false),
+                Column::new("count", false),
+            ],
+        );
+        let mut table = Table::new(schema, new_bptree()).unwrap();
+        insert_count(&mut table, 10);
+
+        assert_eq!(table.primary_key(), Some("name".to_string()));
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Vec<Vec<Value>>>(),
+            vec![vec![Value::Null, Value::Integer(10)]]
+        );
+    }
+
+    #[test]
+    fn rows_are_selected_in_key_order() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("count", false)],
+        );
+        let mut table = Table::new(schema.clone(), new_bptree()).unwrap();
+        type RowTree = crate::bptree::BPTree<Key, Vec<Value>>;
+        let insert_ids = |table: &mut Table<RowTree>, ids: Vec<i64>| {
+            for id in ids {
+                table
+                    .insert_row(vec![Value::Integer(id), Value::Integer(id * 10)])
+                    .unwrap();
+            }
+        };
+        let ids = |table: &Table<RowTree>| {
+            table
+                .select_rows()
+                .unwrap()
+                .map(|row| row[0].clone())
+                .collect::<Vec<Value>>()
+        };
+        let expected = (1..=9).map(Value::Integer).collect::<Vec<Value>>();
+
+        insert_ids(&mut table, vec![5, 2, 8, 1, 9, 3, 7, 4, 6]);
+        assert_eq!(ids(&table), expected);
+
+        table.truncate();
+        insert_ids(&mut table, vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(ids(&table), expected);
+
+        executor::Table::vacuum(&mut table);
+        assert_eq!(ids(&table), expected);
+
+        let mut rows = table.select_rows().unwrap().collect::<Vec<Vec<Value>>>();
+        rows.reverse();
+        table.restore(schema, rows).unwrap();
+        assert_eq!(ids(&table), expected);
+    }
+
+    #[test]
+    fn rows_are_fetched_by_key() {
+        let schema = ast::TableSchema::new_with_primary_key(
+            "orchards",
+            vec![
+                Column::new("farm", false),
+                Column::new("row", false),
+                Column::new("trees", false),
+            ],
+            vec!["farm".to_string(), "row".to_string()],
+        );
+        let mut table = Table::new(schema, new_bptree()).unwrap();
+        let row = vec![Value::Integer(1), Value::Integer(2), Value::Integer(20)];
+        table.insert_row(row.clone()).unwrap();
+
+        assert_eq!(
+            table.get(&[Value::Integer(1), Value::Integer(2)]),
+            Ok(Some(row))
+        );
+        assert_eq!(table.get(&[Value::Integer(2), Value::Integer(1)]), Ok(None));
+        assert_eq!(
+            table.get(&[Value::Integer(1)]),
+            Err("table orchards is keyed by 2 columns but 1 values were supplied".to_string())
+        );
+    }
+
+    #[test]
+    fn rows_inserted_together_match_rows_inserted_one_at_a_time() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("count", false)],
+        );
+        let row = |id: Value, count: i64| vec![id, Value::Integer(count)];
+        let batches = vec![
+            vec![
+                row(Value::Integer(5), 50),
+                row(Value::Null, 60),
+                row(Value::Integer(2), 20),
+            ],
+            vec![row(Value::Null, 70), row(Value::Integer(3), 30)],
+        ];
+
+        let mut together = Table::new(schema.clone(), new_bptree()).unwrap();
+        let mut one_at_a_time = Table::new(schema, new_bptree()).unwrap();
+        for batch in batches {
+            together.insert_rows(batch.clone()).unwrap();
+            for row in batch {
+                one_at_a_time.insert_row(row).unwrap();
+            }
+        }
+
+        assert_eq!(
+            together.select_rows().unwrap().collect::<Vec<Vec<Value>>>(),
+            one_at_a_time
+                .select_rows()
+                .unwrap()
+                .collect::<Vec<Vec<Value>>>()
+        );
+        assert_eq!(together.num_rows, 5);
+        assert_eq!(
+            executor::Table::last_inserted_row(&together),
+            Some(row(Value::Integer(3), 30))
+        );
+        insert_count(&mut together, 80);
+        assert_eq!(
+            executor::Table::last_inserted_row(&together),
+            Some(row(Value::Integer(8), 80))
+        );
+    }
+
+    #[test]
+    fn rows_inserted_together_are_all_rejected_if_one_is() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("count", false)],
+        );
+        let row = |id: Value, count: i64| vec![id, Value::Integer(count)];
+        let mut table = Table::new(schema, new_bptree()).unwrap();
+        table.insert_row(row(Value::Integer(1), 10)).unwrap();
+
+        assert_eq!(
+            table.insert_rows(vec![row(Value::Null, 20), row(Value::Integer(1), 30)]),
+            Err("duplicate entry: 1".to_string())
+        );
+        assert_eq!(
+            table.insert_rows(vec![row(Value::Integer(4), 40), row(Value::Integer(4), 50)]),
+            Err("duplicate entry: 4".to_string())
+        );
+        assert_eq!(
+            table.insert_rows(vec![row(Value::Null, 20), vec![Value::Integer(6)]]),
+            Err("table apples has 1 columns but 2 values were supplied".to_string())
+        );
+
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Vec<Vec<Value>>>(),
+            vec![row(Value::Integer(1), 10)]
+        );
+        insert_count(&mut table, 20);
+        assert_eq!(
+            executor::Table::last_inserted_row(&table),
+            Some(row(Value::Integer(2), 20))
+        );
+    }
+
+    #[test]
+    fn explicit_primary_key_larger_than_rowid_advances_it() {
+        let schema = ast::TableSchema::new(
+            "apples",
+            vec![Column::new("id", true), Column::new("count", false)],
+        );
+        let mut table = Table::new(schema, new_bptree()).unwrap();
+        insert_count(&mut table, 10);
+        table
+            .insert_row(vec![Value::Integer(7), Value::Integer(70)])
+            .unwrap();
+        insert_count(&mut table, 80);
+
+        assert_eq!(
+            table.select_rows().unwrap().collect::<Vec<Vec<Value>>>(),
+            vec![
+                vec![Value::Integer(1), Value::Integer(10)],
+                vec![Value::Integer(7), Value::Integer(70)],
+                vec![Value::Integer(8), Value::Integer(80)],
+            ]
+        );
+    }
 }
@@ -0,0 +1,278 @@
+use crate::ast::{self, Value};
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+pub trait RowSerializer {
+    /// Fails if the row holds a parameter that was never bound to a value
+    fn serialize(&self, row: &[Value]) -> Result<Vec<u8>, String>;
+    fn deserialize(&self, bytes: &[u8], schema: &ast::TableSchema) -> Result<Vec<Value>, String>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RowFormat {
+    Binary,
+    Json,
+}
+
+impl RowFormat {
+    pub fn serializer(&self) -> Box<dyn RowSerializer> {
+        match self {
+            RowFormat::Binary => Box::new(BinarySerializer {}),
+            RowFormat::Json => Box::new(JsonSerializer {}),
+        }
+    }
+}
+
+const NULL_TAG: u8 = 0;
+const INTEGER_TAG: u8 = 1;
+const TEXT_TAG: u8 = 2;
+const BOOLEAN_TAG: u8 = 3;
+const BLOB_TAG: u8 = 4;
+
+/// Compact encoding where NULLs and integers take up exactly
+/// `VALUE_BYTE_SIZE` bytes. Text takes up the same tag and big-endian length
+/// followed by its UTF-8 bytes, a blob the same followed by its bytes, and a
+/// boolean its tag and a single byte.
+pub struct BinarySerializer {}
+
+impl BinarySerializer {
+    pub const VALUE_BYTE_SIZE: usize = 9;
+}
+
+impl RowSerializer for BinarySerializer {
+    fn serialize(&self, row: &[Value]) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::with_capacity(row.len() * BinarySerializer::VALUE_BYTE_SIZE);
+        for value in row {
+            match value {
+                Value::Null => {
+                    buf.push(NULL_TAG);
+                    buf.extend_from_slice(&[0; 8]);
+                }
+                Value::Integer(i) => {
+                    buf.push(INTEGER_TAG);
+                    buf.extend_from_slice(&i.to_be_bytes());
+                }
+                Value::Text(s) => {
+                    buf.push(TEXT_TAG);
+                    buf.extend_from_slice(&(s.len() as u64).to_be_bytes());
+                    buf.extend_from_slice(s.as_bytes());
+                }
+                Value::Blob(bytes) => {
+                    buf.push(BLOB_TAG);
+                    buf.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+                    buf.extend_from_slice(bytes);
+                }
+                Value::Boolean(b) => {
+                    buf.push(BOOLEAN_TAG);
+                    buf.push(*b as u8);
+                }
+                Value::Parameter(index) => return Err(format!("unbound parameter ?{}", index)),
+            }
+        }
+
+        Ok(buf)
+    }
+
+    fn deserialize(&self, bytes: &[u8], schema: &ast::TableSchema) -> Result<Vec<Value>, String> {
+        let num_columns = schema.columns.len();
+        let truncated = || {
+            format!(
+                "expected {} columns but the row is truncated after {} bytes",
+                num_columns,
+                bytes.len()
+            )
+        };
+
+        let mut row = Vec::with_capacity(num_columns);
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let tag = bytes[offset];
+            offset += 1;
+            if tag == BOOLEAN_TAG {
+                let payload = take(bytes, &mut offset, 1).ok_or_else(truncated)?;
+                match payload[0] {
+                    0 => row.push(Value::Boolean(false)),
+                    1 => row.push(Value::Boolean(true)),
+                    byte => return Err(format!("invalid boolean: {}", byte)),
+                }
+                continue;
+            }
+
+            let mut payload = [0; 8];
+            payload.copy_from_slice(take(bytes, &mut offset, 8).ok_or_else(truncated)?);
+            match tag {
+                NULL_TAG => row.push(Value::Null),
+                INTEGER_TAG => row.push(Value::Integer(i64::from_be_bytes(payload))),
+                TEXT_TAG => {
+                    let len = u64::from_be_bytes(payload) as usize;
+                    let text = take(bytes, &mut offset, len).ok_or_else(truncated)?;
+                    let text = String::from_utf8(text.to_vec()).map_err(|err| err.to_string())?;
+                    row.push(Value::Text(text));
+                }
+                BLOB_TAG => {
+                    let len = u64::from_be_bytes(payload) as usize;
+                    let bytes = take(bytes, &mut offset, len).ok_or_else(truncated)?;
+                    row.push(Value::Blob(bytes.to_vec()));
+                }
+                tag => return Err(format!("unknown value tag: {}", tag)),
+            }
+        }
+
+        if row.len() != num_columns {
+            return Err(format!(
+                "expected {} columns but got {}",
+                num_columns,
+                row.len()
+            ));
+        }
+
+        Ok(row)
+    }
+}
+
+/// The `len` bytes at `offset`, which is moved past them
+fn take<'a>(bytes: &'a [u8], offset: &mut usize, len: usize) -> Option<&'a [u8]> {
+    let taken = bytes.get(*offset..*offset + len)?;
+    *offset += len;
+    Some(taken)
+}
+
+/// Human readable encoding that stores a row as a JSON array
+pub struct JsonSerializer {}
+
+impl RowSerializer for JsonSerializer {
+    fn serialize(&self, row: &[Value]) -> Result<Vec<u8>, String> {
+        let values = row
+            .iter()
+            .map(Value::to_json)
+            .collect::<Result<Vec<serde_json::Value>, String>>()?;
+
+        serde_json::to_vec(&values).map_err(|err| err.to_string())
+    }
+
+    fn deserialize(&self, bytes: &[u8], schema: &ast::TableSchema) -> Result<Vec<Value>, String> {
+        let values: Vec<serde_json::Value> =
+            serde_json::from_slice(bytes).map_err(|err| err.to_string())?;
+        if values.len() != schema.columns.len() {
+            return Err(format!(
+                "expected {} values but got {}",
+                schema.columns.len(),
+                values.len()
+            ));
+        }
+
+        values
+            .iter()
+            .map(|value| {
+                Value::from_json(value).ok_or_else(|| format!("unsupported value: {}", value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Column;
+    use pretty_assertions::assert_eq;
+
+    fn schema() -> ast::TableSchema {
+        ast::TableSchema::new(
+            "apples",
+            vec![
+                Column::new("id", true),
+                Column::new("slices", false),
+                Column::new("seeds", false),
+            ],
+        )
+    }
+
+    fn row() -> Vec<Value> {
+        vec![Value::Integer(1), Value::Null, Value::Integer(-42)]
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let serializer = RowFormat::Binary.serializer();
+        let bytes = serializer.serialize(&row()).unwrap();
+        assert_eq!(serializer.deserialize(&bytes, &schema()), Ok(row()));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let serializer = RowFormat::Json.serializer();
+        let bytes = serializer.serialize(&row()).unwrap();
+        assert_eq!(String::from_utf8(bytes.clone()).unwrap(), "[1,null,-42]");
+        assert_eq!(serializer.deserialize(&bytes, &schema()), Ok(row()));
+    }
+
+    #[test]
+    fn text_round_trips_in_both_formats() {
+        let row = vec![
+            Value::Integer(1),
+            Value::Text("it's a Granny Smith".to_string()),
+            Value::Text("".to_string()),
+        ];
+        for format in &[RowFormat::Binary, RowFormat::Json] {
+            let serializer = format.serializer();
+            let bytes = serializer.serialize(&row).unwrap();
+            assert_eq!(serializer.deserialize(&bytes, &schema()), Ok(row.clone()));
+        }
+    }
+
+    #[test]
+    fn booleans_round_trip_in_both_formats() {
+        let row = vec![
+            Value::Integer(1),
+            Value::Boolean(true),
+            Value::Boolean(false),
+        ];
+        for format in &[RowFormat::Binary, RowFormat::Json] {
+            let serializer = format.serializer();
+            let bytes = serializer.serialize(&row).unwrap();
+            assert_eq!(serializer.deserialize(&bytes, &schema()), Ok(row.clone()));
+        }
+    }
+
+    #[test]
+    fn blobs_with_null_bytes_round_trip_in_both_formats() {
+        let row = vec![
+            Value::Integer(1),
+            Value::Blob(vec![0, 72, 0, 0, 255]),
+            Value::Blob(vec![]),
+        ];
+        for format in &[RowFormat::Binary, RowFormat::Json] {
+            let serializer = format.serializer();
+            let bytes = serializer.serialize(&row).unwrap();
+            assert_eq!(serializer.deserialize(&bytes, &schema()), Ok(row.clone()));
+        }
+    }
+
+    #[test]
+    fn unbound_parameters_are_not_serialized() {
+        let row = vec![Value::Integer(1), Value::Parameter(3), Value::Null];
+        for format in &[RowFormat::Binary, RowFormat::Json] {
+            assert_eq!(
+                format.serializer().serialize(&row),
+                Err("unbound parameter ?3".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn binary_byte_length_is_stable() {
+        let serializer = BinarySerializer {};
+        let small = serializer
+            .serialize(&[Value::Integer(0), Value::Null, Value::Null])
+            .unwrap();
+        let large = serializer
+            .serialize(&[
+                Value::Integer(i64::max_value()),
+                Value::Integer(i64::min_value()),
+                Value::Integer(1),
+            ])
+            .unwrap();
+        assert_eq!(small.len(), 3 * BinarySerializer::VALUE_BYTE_SIZE);
+        assert_eq!(large.len(), small.len());
+    }
+}